@@ -60,6 +60,23 @@ impl Aabb {
         }
     }
 
+    pub fn center(&self) -> Point3 {
+        Point3::new(
+            (self.x.min + self.x.max) / 2.0,
+            (self.y.min + self.y.max) / 2.0,
+            (self.z.min + self.z.max) / 2.0,
+        )
+    }
+
+    pub fn diagonal(&self) -> DVec3 {
+        DVec3::new(self.x.size(), self.y.size(), self.z.size())
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let d = self.diagonal();
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
     pub fn hit(&self, ray: &Ray, ray_t: Interval) -> bool {
         for a in 0..3 {
             // let t0 = f64::min(
@@ -0,0 +1,80 @@
+use crate::{color::Color, texture::Texture, Point3};
+use glam::DVec3;
+use std::{f64::consts::PI, sync::Arc};
+
+/// What a ray sees when it leaves the scene without hitting anything.
+pub trait Background: Sync + Send {
+    /// Returns the radiance arriving from the unit `direction`.
+    fn radiance(&self, direction: DVec3) -> Color;
+
+    /// Samples a direction towards the background for next-event estimation, returning it with
+    /// its radiance and solid angle density. Backgrounds that are not worth sampling directly
+    /// return `None` and are only reached by rays scattered off surfaces.
+    fn sample(&self, _u: f64, _v: f64) -> Option<(DVec3, Color, f64)> {
+        None
+    }
+
+    /// Returns the solid angle density with which `sample` generates the unit `direction`.
+    fn pdf(&self, _direction: DVec3) -> f64 {
+        0.0
+    }
+}
+
+/// The same color in every direction.
+pub struct SolidBackground {
+    color: Color,
+}
+
+impl SolidBackground {
+    pub fn new(color: Color) -> Self {
+        Self { color }
+    }
+}
+
+impl Background for SolidBackground {
+    fn radiance(&self, _direction: DVec3) -> Color {
+        self.color
+    }
+}
+
+/// A vertical gradient blending from `bottom` straight down to `top` straight up.
+pub struct GradientBackground {
+    bottom: Color,
+    top: Color,
+}
+
+impl GradientBackground {
+    pub fn new(bottom: Color, top: Color) -> Self {
+        Self { bottom, top }
+    }
+}
+
+impl Background for GradientBackground {
+    fn radiance(&self, direction: DVec3) -> Color {
+        let t = 0.5 * (direction.y + 1.0);
+
+        self.bottom.lerp(self.top, t)
+    }
+}
+
+/// Looks up a texture by direction with an equirectangular mapping, where `u` goes around the
+/// +Y axis starting from +X and `v` runs from straight down to straight up.
+pub struct TextureBackground {
+    texture: Arc<dyn Texture>,
+}
+
+impl TextureBackground {
+    pub fn new(texture: Arc<dyn Texture>) -> Self {
+        Self { texture }
+    }
+}
+
+impl Background for TextureBackground {
+    fn radiance(&self, direction: DVec3) -> Color {
+        let theta = direction.y.clamp(-1.0, 1.0).acos();
+        let phi = direction.z.atan2(direction.x).rem_euclid(2.0 * PI);
+
+        self.texture
+            .value(phi / (2.0 * PI), 1.0 - theta / PI, &Point3::from(direction))
+    }
+}
@@ -1,14 +1,42 @@
 use crate::{random_f64_range, random_in_unit_disk, ray::Ray, Point3};
 use glam::DVec3;
 
-pub struct Camera {
+/// Turns points on the image into primary rays.
+pub trait Camera: Sync + Send {
+    /// Prepares the camera to render an image with the given width over height.
+    fn init(&mut self, aspect_ratio: f64);
+
+    /// Returns the ray through the point `(s, t)` of the image, where `(0, 0)` is the lower left
+    /// corner and `(1, 1)` the upper right, or `None` where the camera does not cover the image.
+    fn get_ray(&self, s: f64, t: f64) -> Option<Ray>;
+}
+
+/// How the camera maps the image to rays.
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub enum Projection {
+    /// Rays fan out from `look_from` to cover the vertical field of view `vfov`
+    #[default]
+    Perspective,
+    /// Rays run parallel to the view direction from a view `height` world units tall, centered
+    /// on `look_from`
+    Orthographic { height: f64 },
+}
+
+/// A camera with a thin lens for depth of field, viewing the scene through a perspective or
+/// orthographic projection.
+#[derive(Clone)]
+pub struct ThinLensCamera {
     // aspect_ratio: f32,
     // viewport_height: f32,
     // viewport_width: f32,
     // focal_length: f32,
+    pub projection: Projection,
     pub vfov: f64,
     pub aperture: f64,
     pub focus_dist: f64,
+    // Moves the view sideways by a fraction of its width without changing the perspective, like
+    // the shift of a tilt-shift lens
+    pub horizontal_shift: f64,
 
     pub look_from: Point3,
     pub look_at: Point3,
@@ -25,12 +53,14 @@ pub struct Camera {
     lens_radius: f64,
 }
 
-impl Default for Camera {
+impl Default for ThinLensCamera {
     fn default() -> Self {
         Self {
+            projection: Projection::default(),
             vfov: 40.0,
             aperture: 0.0,
             focus_dist: 10.0,
+            horizontal_shift: 0.0,
             look_from: Point3::new(0.0, 0.0, -1.0),
             look_at: Point3::new(0.0, 0.0, 0.0),
             vup: DVec3::new(0.0, 1.0, 0.0),
@@ -46,34 +76,62 @@ impl Default for Camera {
     }
 }
 
-impl Camera {
-    pub fn init(&mut self, aspect_ratio: f64) {
-        let theta = self.vfov.to_radians();
-        let h = (theta / 2.0).tan();
-        let viewport_height = 2.0 * h;
-        let viewport_width = aspect_ratio * viewport_height;
-
+impl Camera for ThinLensCamera {
+    fn init(&mut self, aspect_ratio: f64) {
         self.w = (self.look_from - self.look_at).normalize();
         self.u = self.vup.cross(self.w).normalize();
         self.v = self.w.cross(self.u);
-
         self.origin = self.look_from;
-        self.horizontal = self.focus_dist * viewport_width * self.u;
-        self.vertical = self.focus_dist * viewport_height * self.v;
-        self.lower_left_corner =
-            self.origin - self.horizontal / 2.0 - self.vertical / 2.0 - self.focus_dist * self.w;
         self.lens_radius = self.aperture / 2.0;
+
+        match self.projection {
+            Projection::Perspective => {
+                let theta = self.vfov.to_radians();
+                let h = (theta / 2.0).tan();
+                let viewport_height = 2.0 * h;
+                let viewport_width = aspect_ratio * viewport_height;
+
+                self.horizontal = self.focus_dist * viewport_width * self.u;
+                self.vertical = self.focus_dist * viewport_height * self.v;
+                self.lower_left_corner = self.origin
+                    - self.horizontal / 2.0
+                    - self.vertical / 2.0
+                    - self.focus_dist * self.w;
+            }
+            Projection::Orthographic { height } => {
+                // The view rectangle lies in the plane of the camera and does not scale with
+                // the focus distance
+                self.horizontal = aspect_ratio * height * self.u;
+                self.vertical = height * self.v;
+                self.lower_left_corner = self.origin - self.horizontal / 2.0 - self.vertical / 2.0;
+            }
+        }
+        self.lower_left_corner += self.horizontal_shift * self.horizontal;
     }
 
-    pub fn get_ray(&self, s: f64, t: f64) -> Ray {
+    fn get_ray(&self, s: f64, t: f64) -> Option<Ray> {
         let rd = self.lens_radius * random_in_unit_disk();
         let offset = self.u * rd.x + self.v * rd.y;
         let ray_time = random_f64_range(0.0, 1.0);
 
-        Ray::new(
-            self.origin + offset,
-            self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin - offset,
-            ray_time,
-        )
+        Some(match self.projection {
+            Projection::Perspective => Ray::new(
+                self.origin + offset,
+                self.lower_left_corner + s * self.horizontal + t * self.vertical
+                    - self.origin
+                    - offset,
+                ray_time,
+            ),
+            Projection::Orthographic { .. } => {
+                // Every ray from the lens passes through the point in focus straight ahead of
+                // its spot on the view rectangle
+                let view_point = self.lower_left_corner + s * self.horizontal + t * self.vertical;
+                Ray::new(
+                    view_point + offset,
+                    -self.focus_dist * self.w - offset,
+                    ray_time,
+                )
+            }
+        })
     }
 }
@@ -4,6 +4,52 @@ use crate::clamp;
 
 pub type Color = DVec3;
 
+/// Returns the relative luminance of a linear RGB color.
+pub fn luminance(color: Color) -> f64 {
+    0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+}
+
+/// Returns the linear RGB color of a black body at the given temperature in kelvin, normalized to
+/// unit luminance.
+pub fn blackbody(kelvin: f64) -> Color {
+    // Second radiation constant hc/k in nm K
+    const C2: f64 = 1.438_776_9e7;
+
+    // Integrate Planck's law against an analytic fit of the CIE 1931 color matching functions
+    let mut xyz = DVec3::ZERO;
+    for wavelength in (380..=780).step_by(5) {
+        let wavelength = wavelength as f64;
+        let radiance = 1.0 / (wavelength.powi(5) * ((C2 / (wavelength * kelvin)).exp() - 1.0));
+        xyz += radiance * cie_color_matching(wavelength);
+    }
+
+    let rgb = xyz_to_rgb(xyz).max(Color::ZERO);
+    rgb / luminance(rgb)
+}
+
+/// Converts a CIE XYZ color to linear sRGB.
+pub fn xyz_to_rgb(xyz: DVec3) -> Color {
+    Color::new(
+        3.2406 * xyz.x - 1.5372 * xyz.y - 0.4986 * xyz.z,
+        -0.9689 * xyz.x + 1.8758 * xyz.y + 0.0415 * xyz.z,
+        0.0557 * xyz.x - 0.2040 * xyz.y + 1.0570 * xyz.z,
+    )
+}
+
+// Multi-lobe Gaussian fit of the CIE 1931 standard observer by Wyman, Sloan and Shirley
+fn cie_color_matching(wavelength: f64) -> DVec3 {
+    let g = |mu: f64, sigma_1: f64, sigma_2: f64| {
+        let sigma = if wavelength < mu { sigma_1 } else { sigma_2 };
+        (-0.5 * ((wavelength - mu) / sigma).powi(2)).exp()
+    };
+
+    DVec3::new(
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    )
+}
+
 pub fn write_color(pixel_color: Color, samples_per_pixel: usize) {
     let mut r = pixel_color.x;
     let mut g = pixel_color.y;
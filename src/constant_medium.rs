@@ -4,16 +4,16 @@ use crate::{
     color::Color,
     hittable::{HitRecord, Hittable},
     interval::Interval,
-    material::Material,
+    material::{Isotropic, Material},
     random_f64,
-    texture::{SolidColor, Texture},
+    texture::Texture,
 };
 use std::sync::Arc;
 
 pub struct ConstantMedium {
     boundary: Arc<dyn Hittable>,
     neg_inv_density: f64,
-    phase_function: Arc<Material>,
+    phase_function: Arc<dyn Material>,
 }
 
 impl ConstantMedium {
@@ -25,7 +25,7 @@ impl ConstantMedium {
         Self {
             boundary,
             neg_inv_density: -1.0 / density,
-            phase_function: Arc::new(Material::Isotropic { albedo: texture }),
+            phase_function: Arc::new(Isotropic::new(texture)),
         }
     }
 
@@ -33,9 +33,7 @@ impl ConstantMedium {
         Self {
             boundary,
             neg_inv_density: -1.0 / density,
-            phase_function: Arc::new(Material::Isotropic {
-                albedo: Arc::new(SolidColor::new(color)),
-            }),
+            phase_function: Arc::new(Isotropic::from_color(color)),
         }
     }
 }
@@ -107,7 +105,7 @@ impl Hittable for ConstantMedium {
             eprintln!("hit_record.point = {}", hit_record.point);
         }
 
-        hit_record.normal = DVec3::new(1.0, 0.0, 0.0); // arbitrary
+        hit_record.normal = DVec3::ZERO; // no surface inside a medium
         hit_record.front_face = true; // arbitrary
         hit_record.material = self.phase_function.clone();
 
@@ -0,0 +1,200 @@
+/// A piecewise-constant 1D distribution over [0, 1) built from non-negative function values,
+/// sampled by inverting its cumulative distribution.
+pub struct Distribution1D {
+    func: Vec<f64>,
+    cdf: Vec<f64>,
+    func_integral: f64,
+}
+
+impl Distribution1D {
+    pub fn new(func: Vec<f64>) -> Self {
+        let n = func.len();
+        let mut cdf = vec![0.0; n + 1];
+        for i in 0..n {
+            cdf[i + 1] = cdf[i] + func[i].abs() / n as f64;
+        }
+
+        let func_integral = cdf[n];
+        if func_integral == 0.0 {
+            // Fall back to a uniform distribution
+            for (i, value) in cdf.iter_mut().enumerate() {
+                *value = i as f64 / n as f64;
+            }
+        } else {
+            for value in cdf.iter_mut() {
+                *value /= func_integral;
+            }
+        }
+
+        Self {
+            func,
+            cdf,
+            func_integral,
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.func.len()
+    }
+
+    pub fn integral(&self) -> f64 {
+        self.func_integral
+    }
+
+    /// Maps a uniform random number to a point in [0, 1), returning the point, its density and
+    /// the index of the segment it falls into.
+    pub fn sample(&self, u: f64) -> (f64, f64, usize) {
+        // Find the last cdf entry that is not greater than u
+        let offset = self
+            .cdf
+            .partition_point(|&value| value <= u)
+            .clamp(1, self.count())
+            - 1;
+
+        let mut du = u - self.cdf[offset];
+        let width = self.cdf[offset + 1] - self.cdf[offset];
+        if width > 0.0 {
+            du /= width;
+        }
+
+        let x = (offset as f64 + du) / self.count() as f64;
+        (x, self.pdf(offset), offset)
+    }
+
+    /// Returns the density of the given segment.
+    pub fn pdf(&self, offset: usize) -> f64 {
+        if self.func_integral == 0.0 {
+            1.0
+        } else {
+            self.func[offset] / self.func_integral
+        }
+    }
+}
+
+/// A piecewise-constant 2D distribution over [0, 1)^2 given as rows of function values, sampled
+/// by picking a row from the marginal distribution and then a column within the row.
+pub struct Distribution2D {
+    conditionals: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    pub fn new(func: &[f64], width: usize, height: usize) -> Self {
+        let conditionals = func
+            .chunks(width)
+            .take(height)
+            .map(|row| Distribution1D::new(row.to_vec()))
+            .collect::<Vec<_>>();
+        let marginal = Distribution1D::new(
+            conditionals
+                .iter()
+                .map(|conditional| conditional.integral())
+                .collect(),
+        );
+
+        Self {
+            conditionals,
+            marginal,
+        }
+    }
+
+    /// Maps two uniform random numbers to a point `(u, v)`, returning it with its density.
+    pub fn sample(&self, u: f64, v: f64) -> ((f64, f64), f64) {
+        let (y, pdf_y, row) = self.marginal.sample(v);
+        let (x, pdf_x, _) = self.conditionals[row].sample(u);
+
+        ((x, y), pdf_x * pdf_y)
+    }
+
+    /// Returns the density of the point `(u, v)`.
+    pub fn pdf(&self, u: f64, v: f64) -> f64 {
+        let row = ((v * self.marginal.count() as f64) as usize).min(self.marginal.count() - 1);
+        let conditional = &self.conditionals[row];
+        let column = ((u * conditional.count() as f64) as usize).min(conditional.count() - 1);
+
+        if self.marginal.integral() == 0.0 {
+            1.0
+        } else {
+            conditional.func[column] / self.marginal.integral()
+        }
+    }
+}
+
+/// Samples an index with probability proportional to its weight in constant time, using Vose's
+/// alias method.
+pub struct AliasTable {
+    bins: Vec<AliasBin>,
+}
+
+struct AliasBin {
+    // Probability of picking this index, and the chance of keeping it once its bin is chosen
+    pmf: f64,
+    q: f64,
+    alias: usize,
+}
+
+impl AliasTable {
+    pub fn new(weights: &[f64]) -> Self {
+        let n = weights.len();
+        let sum = weights.iter().map(|weight| weight.max(0.0)).sum::<f64>();
+        let mut bins = weights
+            .iter()
+            .enumerate()
+            .map(|(index, weight)| {
+                // Fall back to a uniform distribution
+                let pmf = if sum > 0.0 {
+                    weight.max(0.0) / sum
+                } else {
+                    1.0 / n as f64
+                };
+                AliasBin {
+                    pmf,
+                    q: pmf * n as f64,
+                    alias: index,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // Pair each bin below the average with one above it, which gives away its excess
+        let (mut under, mut over): (Vec<usize>, Vec<usize>) =
+            (0..n).partition(|&index| bins[index].q < 1.0);
+        while let (Some(&small), Some(&large)) = (under.last(), over.last()) {
+            under.pop();
+            over.pop();
+            bins[small].alias = large;
+            bins[large].q += bins[small].q - 1.0;
+            if bins[large].q < 1.0 {
+                under.push(large);
+            } else {
+                over.push(large);
+            }
+        }
+
+        // Whatever is left over is only off by rounding
+        for index in under.into_iter().chain(over) {
+            bins[index].q = 1.0;
+        }
+
+        Self { bins }
+    }
+
+    pub fn count(&self) -> usize {
+        self.bins.len()
+    }
+
+    /// Maps a uniform random number to an index, returning it with its probability.
+    pub fn sample(&self, u: f64) -> (usize, f64) {
+        let scaled = u * self.count() as f64;
+        let offset = (scaled as usize).min(self.count() - 1);
+        let up = (scaled - offset as f64).min(1.0 - f64::EPSILON);
+
+        let bin = &self.bins[offset];
+        let index = if up < bin.q { offset } else { bin.alias };
+        (index, self.bins[index].pmf)
+    }
+
+    /// Returns the probability of sampling the given index.
+    pub fn pmf(&self, index: usize) -> f64 {
+        self.bins[index].pmf
+    }
+}
@@ -0,0 +1,135 @@
+use crate::{
+    background::Background,
+    color::{luminance, Color},
+    distribution::Distribution2D,
+};
+use glam::DVec3;
+use std::{f64::consts::PI, path::Path};
+
+/// Image-based lighting from an equirectangular high dynamic range image, such as a `.hdr` or
+/// `.exr` file, surrounding the scene at infinity. The top row of the image looks along +Y and
+/// the image is importance-sampled by luminance.
+pub struct EnvironmentLight {
+    pixels: Vec<Color>,
+    width: usize,
+    height: usize,
+    rotation: f64,
+    intensity: f64,
+    distribution: Distribution2D,
+}
+
+impl EnvironmentLight {
+    pub fn new(path: &Path) -> Self {
+        let image = image::open(path)
+            .expect("environment map not found")
+            .to_rgb32f();
+        let pixels = image
+            .pixels()
+            .map(|pixel| Color::new(pixel[0] as f64, pixel[1] as f64, pixel[2] as f64))
+            .collect::<Vec<_>>();
+
+        Self::from_pixels(pixels, image.width() as usize, image.height() as usize)
+    }
+
+    /// Bakes a radiance function of the direction into an environment map of the given size.
+    pub fn from_fn(width: usize, height: usize, radiance: impl Fn(DVec3) -> Color) -> Self {
+        let pixels = (0..width * height)
+            .map(|index| {
+                let u = ((index % width) as f64 + 0.5) / width as f64;
+                let v = ((index / width) as f64 + 0.5) / height as f64;
+                let theta = PI * v;
+                let phi = 2.0 * PI * u;
+
+                radiance(DVec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.cos(),
+                    theta.sin() * phi.sin(),
+                ))
+            })
+            .collect::<Vec<_>>();
+
+        Self::from_pixels(pixels, width, height)
+    }
+
+    fn from_pixels(pixels: Vec<Color>, width: usize, height: usize) -> Self {
+        // Weight each pixel by the solid angle it covers, which shrinks towards the poles
+        let weights = pixels
+            .iter()
+            .enumerate()
+            .map(|(index, &pixel)| {
+                let theta = PI * ((index / width) as f64 + 0.5) / height as f64;
+                luminance(pixel).max(0.0) * theta.sin()
+            })
+            .collect::<Vec<_>>();
+
+        Self {
+            distribution: Distribution2D::new(&weights, width, height),
+            pixels,
+            width,
+            height,
+            rotation: 0.0,
+            intensity: 1.0,
+        }
+    }
+
+    /// Rotates the environment around the +Y axis by the given angle in degrees.
+    pub fn with_rotation(mut self, rotation: f64) -> Self {
+        self.rotation = rotation.to_radians();
+        self
+    }
+
+    pub fn with_intensity(mut self, intensity: f64) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    fn direction_to_uv(&self, direction: DVec3) -> (f64, f64) {
+        let theta = direction.y.clamp(-1.0, 1.0).acos();
+        let phi = (direction.z.atan2(direction.x) - self.rotation).rem_euclid(2.0 * PI);
+
+        (phi / (2.0 * PI), theta / PI)
+    }
+
+    fn uv_to_direction(&self, u: f64, v: f64) -> DVec3 {
+        let theta = PI * v;
+        let phi = 2.0 * PI * u + self.rotation;
+
+        DVec3::new(
+            theta.sin() * phi.cos(),
+            theta.cos(),
+            theta.sin() * phi.sin(),
+        )
+    }
+}
+
+impl Background for EnvironmentLight {
+    fn radiance(&self, direction: DVec3) -> Color {
+        let (u, v) = self.direction_to_uv(direction);
+        let i = ((u * self.width as f64) as usize).min(self.width - 1);
+        let j = ((v * self.height as f64) as usize).min(self.height - 1);
+
+        self.intensity * self.pixels[i + j * self.width]
+    }
+
+    fn sample(&self, u: f64, v: f64) -> Option<(DVec3, Color, f64)> {
+        let ((u, v), pdf_uv) = self.distribution.sample(u, v);
+        let sin_theta = (PI * v).sin();
+        if pdf_uv == 0.0 || sin_theta == 0.0 {
+            return None;
+        }
+
+        let direction = self.uv_to_direction(u, v);
+        let pdf = pdf_uv / (2.0 * PI * PI * sin_theta);
+        Some((direction, self.radiance(direction), pdf))
+    }
+
+    fn pdf(&self, direction: DVec3) -> f64 {
+        let (u, v) = self.direction_to_uv(direction);
+        let sin_theta = (PI * v).sin();
+        if sin_theta == 0.0 {
+            return 0.0;
+        }
+
+        self.distribution.pdf(u, v) / (2.0 * PI * PI * sin_theta)
+    }
+}
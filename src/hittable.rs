@@ -1,6 +1,13 @@
 use crate::{
-    aabb::Aabb, interval::Interval, material::Material, ray::Ray, texture::SolidColor, DVec3,
-    Point3,
+    aabb::Aabb,
+    color::Color,
+    interval::Interval,
+    light::{Light, LightLinks},
+    material::{Lambertian, Material},
+    random_f64,
+    ray::{Ray, RayKind},
+    texture::Texture,
+    DVec3, Point3,
 };
 use std::sync::Arc;
 
@@ -8,11 +15,18 @@ use std::sync::Arc;
 pub struct HitRecord {
     pub point: Point3,
     pub normal: DVec3,
-    pub material: Arc<Material>,
+    pub material: Arc<dyn Material>,
     pub t: f64,
     pub u: f64,
     pub v: f64,
     pub front_face: bool,
+    // Partial derivatives of the hit point with respect to u and v, zero if not parameterized
+    pub dpdu: DVec3,
+    pub dpdv: DVec3,
+    // Restricts which lights illuminate the hit point, all of them if not set
+    pub light_links: Option<Arc<LightLinks>>,
+    // The light in the scene's light list that the hit surface belongs to, if any
+    pub light: Option<Arc<dyn Light>>,
 }
 
 impl HitRecord {
@@ -31,13 +45,15 @@ impl HitRecord {
         Self {
             point: (0.0, 0.0, 0.0).into(),
             normal: (0.0, 0.0, 0.0).into(),
-            material: Arc::new(Material::Lambertian {
-                albedo: Arc::new(SolidColor::from_rgb(0.0, 0.0, 0.0)),
-            }),
+            material: Arc::new(Lambertian::from_color(Color::new(0.0, 0.0, 0.0))),
             t: 0.0,
             u: 0.0,
             v: 0.0,
             front_face: false,
+            dpdu: DVec3::ZERO,
+            dpdv: DVec3::ZERO,
+            light_links: None,
+            light: None,
         }
     }
 }
@@ -48,16 +64,50 @@ pub trait Hittable: Sync + Send {
     fn bounding_box(&self) -> &Aabb;
 }
 
+/// An opacity mask for cut-out surfaces such as foliage and fences. Rays pass through the parts of
+/// a surface where the mask is transparent and keep looking for a hit further along.
+#[derive(Clone)]
+pub enum AlphaMask {
+    /// Surfaces are opaque where the opacity is at least the threshold
+    Threshold {
+        opacity: Arc<dyn Texture>,
+        threshold: f64,
+    },
+    /// Surfaces are opaque with a probability equal to the opacity
+    Stochastic { opacity: Arc<dyn Texture> },
+}
+
+impl AlphaMask {
+    pub fn threshold(opacity: Arc<dyn Texture>, threshold: f64) -> Self {
+        Self::Threshold { opacity, threshold }
+    }
+
+    pub fn stochastic(opacity: Arc<dyn Texture>) -> Self {
+        Self::Stochastic { opacity }
+    }
+
+    /// Returns true if a ray hitting the surface at the given point should pass through it.
+    pub fn is_transparent(&self, u: f64, v: f64, point: &Point3) -> bool {
+        match self {
+            Self::Threshold { opacity, threshold } => {
+                opacity.scalar_value(u, v, point) < *threshold
+            }
+            Self::Stochastic { opacity } => random_f64() >= opacity.scalar_value(u, v, point),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Sphere {
     center: Point3,
     radius: f64,
-    material: Arc<Material>,
+    material: Arc<dyn Material>,
     bounding_box: Aabb,
+    alpha_mask: Option<AlphaMask>,
 }
 
 impl Sphere {
-    pub fn new(center: Point3, radius: f64, material: Arc<Material>) -> Self {
+    pub fn new(center: Point3, radius: f64, material: Arc<dyn Material>) -> Self {
         let radius_vec = DVec3::new(radius, radius, radius);
 
         Self {
@@ -65,9 +115,15 @@ impl Sphere {
             radius,
             material,
             bounding_box: Aabb::from_points(&(center - radius_vec), &(center + radius_vec)),
+            alpha_mask: None,
         }
     }
 
+    pub fn with_alpha_mask(mut self, alpha_mask: AlphaMask) -> Self {
+        self.alpha_mask = Some(alpha_mask);
+        self
+    }
+
     fn get_sphere_uv(point: &Point3) -> (f64, f64) {
         // p: a given point on the sphere of radius one, centered at the origin.
         // u: returned value [0,1] of angle around the Y axis from X=-1.
@@ -83,6 +139,22 @@ impl Sphere {
         let v = theta / std::f64::consts::PI;
         (u, v)
     }
+
+    fn get_sphere_tangents(point: &Point3) -> (DVec3, DVec3) {
+        // Derivatives of the unit sphere parameterization used by get_sphere_uv, where
+        // p = (-cos(phi) sin(theta), -cos(theta), sin(phi) sin(theta))
+        let theta = (-point.y).acos();
+        let phi = (-point.z).atan2(point.x) + std::f64::consts::PI;
+        let (sin_theta, cos_theta) = theta.sin_cos();
+        let (sin_phi, cos_phi) = phi.sin_cos();
+
+        let dpdphi = DVec3::new(sin_phi * sin_theta, 0.0, cos_phi * sin_theta);
+        let dpdtheta = DVec3::new(-cos_phi * cos_theta, sin_theta, sin_phi * cos_theta);
+        (
+            2.0 * std::f64::consts::PI * dpdphi,
+            std::f64::consts::PI * dpdtheta,
+        )
+    }
 }
 
 impl Hittable for Sphere {
@@ -100,31 +172,45 @@ impl Hittable for Sphere {
 
         let sqrt_discriminant = discriminant.sqrt();
 
-        // find the nearest root that lies in the acceptable range
-        let mut root = (-half_b - sqrt_discriminant) / a;
-        if !ray_t.contains(root) {
-            root = (-half_b + sqrt_discriminant) / a;
+        // find the nearest root that lies in the acceptable range and is not cut out by the mask
+        let roots = [
+            (-half_b - sqrt_discriminant) / a,
+            (-half_b + sqrt_discriminant) / a,
+        ];
+        for root in roots {
             if !ray_t.contains(root) {
-                return None;
+                continue;
             }
-        }
 
-        let mut hit_record = HitRecord::empty();
-        hit_record.t = root;
-        hit_record.point = ray.at(hit_record.t);
+            let point = ray.at(root);
+            let outward_normal = (point - self.center) / self.radius;
+            let (u, v) = Self::get_sphere_uv(&outward_normal);
+            if let Some(alpha_mask) = &self.alpha_mask {
+                if alpha_mask.is_transparent(u, v, &point) {
+                    continue;
+                }
+            }
 
-        let outward_normal = (hit_record.point - self.center) / self.radius;
-        let (front_face, normal) = hit_record.face_normal(ray, outward_normal);
-        hit_record.normal = normal;
-        hit_record.front_face = front_face;
+            let mut hit_record = HitRecord::empty();
+            hit_record.t = root;
+            hit_record.point = point;
 
-        let (u, v) = Self::get_sphere_uv(&outward_normal);
-        hit_record.u = u;
-        hit_record.v = v;
+            let (front_face, normal) = hit_record.face_normal(ray, outward_normal);
+            hit_record.normal = normal;
+            hit_record.front_face = front_face;
 
-        hit_record.material = self.material.clone();
+            hit_record.u = u;
+            hit_record.v = v;
+            let (dpdu, dpdv) = Self::get_sphere_tangents(&outward_normal);
+            hit_record.dpdu = self.radius * dpdu;
+            hit_record.dpdv = self.radius * dpdv;
 
-        Some(hit_record)
+            hit_record.material = self.material.clone();
+
+            return Some(hit_record);
+        }
+
+        None
     }
 
     fn bounding_box(&self) -> &Aabb {
@@ -137,12 +223,18 @@ pub struct MovingSphere {
     center_1: Point3, // center at time = 1
     center_vec: DVec3,
     radius: f64,
-    material: Arc<Material>,
+    material: Arc<dyn Material>,
     bounding_box: Aabb,
+    alpha_mask: Option<AlphaMask>,
 }
 
 impl MovingSphere {
-    pub fn new(center_0: Point3, center_1: Point3, radius: f64, material: Arc<Material>) -> Self {
+    pub fn new(
+        center_0: Point3,
+        center_1: Point3,
+        radius: f64,
+        material: Arc<dyn Material>,
+    ) -> Self {
         let radius_vec = DVec3::new(radius, radius, radius);
         let box_0 = Aabb::from_points(&(center_0 - radius_vec), &(center_0 + radius_vec));
         let box_1 = Aabb::from_points(&(center_1 - radius_vec), &(center_1 + radius_vec));
@@ -154,9 +246,15 @@ impl MovingSphere {
             radius,
             material,
             bounding_box: Aabb::from_aabbs(&box_0, &box_1),
+            alpha_mask: None,
         }
     }
 
+    pub fn with_alpha_mask(mut self, alpha_mask: AlphaMask) -> Self {
+        self.alpha_mask = Some(alpha_mask);
+        self
+    }
+
     pub fn center(&self, time: f64) -> Point3 {
         // Linearly interpolate from center_0 to center_1 according to time,
         // where
@@ -168,7 +266,8 @@ impl MovingSphere {
 
 impl Hittable for MovingSphere {
     fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord> {
-        let oc = ray.origin - self.center(ray.time);
+        let center = self.center(ray.time);
+        let oc = ray.origin - center;
         let a = ray.direction.length_squared();
         let half_b = oc.dot(ray.direction);
         let c = oc.length_squared() - self.radius * self.radius;
@@ -181,26 +280,45 @@ impl Hittable for MovingSphere {
 
         let sqrt_discriminant = discriminant.sqrt();
 
-        // find the nearest root that lies in the acceptable range
-        let mut root = (-half_b - sqrt_discriminant) / a;
-        if !ray_t.contains(root) {
-            root = (-half_b + sqrt_discriminant) / a;
+        // find the nearest root that lies in the acceptable range and is not cut out by the mask
+        let roots = [
+            (-half_b - sqrt_discriminant) / a,
+            (-half_b + sqrt_discriminant) / a,
+        ];
+        for root in roots {
             if !ray_t.contains(root) {
-                return None;
+                continue;
             }
-        }
 
-        let mut hit_record = HitRecord::empty();
-        hit_record.t = root;
-        hit_record.point = ray.at(hit_record.t);
+            let point = ray.at(root);
+            let outward_normal = (point - center) / self.radius;
+            let (u, v) = Sphere::get_sphere_uv(&outward_normal);
+            if let Some(alpha_mask) = &self.alpha_mask {
+                if alpha_mask.is_transparent(u, v, &point) {
+                    continue;
+                }
+            }
 
-        let outward_normal = (hit_record.point - self.center(ray.time)) / self.radius;
-        let (front_face, normal) = hit_record.face_normal(ray, outward_normal);
-        hit_record.normal = normal;
-        hit_record.front_face = front_face;
-        hit_record.material = self.material.clone();
+            let mut hit_record = HitRecord::empty();
+            hit_record.t = root;
+            hit_record.point = point;
 
-        Some(hit_record)
+            let (front_face, normal) = hit_record.face_normal(ray, outward_normal);
+            hit_record.normal = normal;
+            hit_record.front_face = front_face;
+
+            hit_record.u = u;
+            hit_record.v = v;
+            let (dpdu, dpdv) = Sphere::get_sphere_tangents(&outward_normal);
+            hit_record.dpdu = self.radius * dpdu;
+            hit_record.dpdv = self.radius * dpdv;
+
+            hit_record.material = self.material.clone();
+
+            return Some(hit_record);
+        }
+
+        None
     }
 
     fn bounding_box(&self) -> &Aabb {
@@ -258,15 +376,16 @@ pub struct Quad {
     q: Point3,
     u: DVec3,
     v: DVec3,
-    material: Arc<Material>,
+    material: Arc<dyn Material>,
     bounding_box: Aabb,
     normal: DVec3,
     d: f64,
     w: DVec3,
+    alpha_mask: Option<AlphaMask>,
 }
 
 impl Quad {
-    pub fn new(q: Point3, u: DVec3, v: DVec3, material: Arc<Material>) -> Self {
+    pub fn new(q: Point3, u: DVec3, v: DVec3, material: Arc<dyn Material>) -> Self {
         let n = u.cross(v);
         let normal = n.normalize();
         let d = normal.dot(q);
@@ -281,11 +400,17 @@ impl Quad {
             normal,
             d,
             w,
+            alpha_mask: None,
         };
         quad.set_bounding_box();
         quad
     }
 
+    pub fn with_alpha_mask(mut self, alpha_mask: AlphaMask) -> Self {
+        self.alpha_mask = Some(alpha_mask);
+        self
+    }
+
     fn set_bounding_box(&mut self) {
         self.bounding_box = Aabb::from_points(&self.q, &(self.q + self.u + self.v));
     }
@@ -324,10 +449,19 @@ impl Hittable for Quad {
         if Self::is_interior(alpha, beta) {
             hit_record.u = alpha;
             hit_record.v = beta;
+            hit_record.dpdu = self.u;
+            hit_record.dpdv = self.v;
         } else {
             return None;
         }
 
+        // Let the ray continue through cut-out parts of the shape
+        if let Some(alpha_mask) = &self.alpha_mask {
+            if alpha_mask.is_transparent(alpha, beta, &intersection) {
+                return None;
+            }
+        }
+
         // Ray hits the 2D shape; set the rest of the hit record and return it
         hit_record.t = t;
         hit_record.point = intersection;
@@ -344,7 +478,7 @@ impl Hittable for Quad {
     }
 }
 
-pub fn create_box(a: &Point3, b: &Point3, material: Arc<Material>) -> HittableList {
+pub fn create_box(a: &Point3, b: &Point3, material: Arc<dyn Material>) -> HittableList {
     // returns the 3D box (six sides) that contains the two opposite vertices a & b
     let mut sides = HittableList::default();
 
@@ -422,7 +556,8 @@ impl Translate {
 impl Hittable for Translate {
     fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord> {
         // Move the ray backwards by the offset
-        let offset_ray = Ray::new(ray.origin - self.offset, ray.direction, ray.time);
+        let offset_ray =
+            Ray::new(ray.origin - self.offset, ray.direction, ray.time).with_kind(ray.kind);
 
         // Determine where (if any) an intersection occurs along the offset_ray
         if let Some(mut hitted_record) = self.object.hit(&offset_ray, ray_t) {
@@ -498,7 +633,7 @@ impl Hittable for RotationY {
         direction[0] = self.cos_theta * ray.direction[0] - self.sin_theta * ray.direction[2];
         direction[2] = self.sin_theta * ray.direction[0] + self.cos_theta * ray.direction[2];
 
-        let rotated_ray = Ray::new(origin, direction, ray.time);
+        let rotated_ray = Ray::new(origin, direction, ray.time).with_kind(ray.kind);
 
         // Determine where (if any) an intersection occurs in object space
         if let Some(mut hitted_record) = self.object.hit(&rotated_ray, ray_t) {
@@ -516,8 +651,19 @@ impl Hittable for RotationY {
             normal[2] = -self.sin_theta * hitted_record.normal[0]
                 + self.cos_theta * hitted_record.normal[2];
 
+            // Rotate the surface tangents the same way
+            let to_world = |vector: DVec3| {
+                DVec3::new(
+                    self.cos_theta * vector.x + self.sin_theta * vector.z,
+                    vector.y,
+                    -self.sin_theta * vector.x + self.cos_theta * vector.z,
+                )
+            };
+
             hitted_record.point = point;
             hitted_record.normal = normal;
+            hitted_record.dpdu = to_world(hitted_record.dpdu);
+            hitted_record.dpdv = to_world(hitted_record.dpdv);
 
             Some(hitted_record)
         } else {
@@ -529,3 +675,100 @@ impl Hittable for RotationY {
         &self.bounding_box
     }
 }
+
+/// Which kinds of rays can see an object.
+#[derive(Clone, Copy)]
+pub struct Visibility {
+    pub camera: bool,
+    pub shadow: bool,
+    pub reflection: bool,
+    pub glossy: bool,
+    pub diffuse: bool,
+    pub transmission: bool,
+}
+
+impl Visibility {
+    pub const ALL: Self = Self {
+        camera: true,
+        shadow: true,
+        reflection: true,
+        glossy: true,
+        diffuse: true,
+        transmission: true,
+    };
+
+    pub fn is_visible_to(&self, kind: RayKind) -> bool {
+        match kind {
+            RayKind::Camera => self.camera,
+            RayKind::Shadow => self.shadow,
+            RayKind::Reflection => self.reflection,
+            RayKind::Glossy => self.glossy,
+            RayKind::Diffuse => self.diffuse,
+            RayKind::Transmission => self.transmission,
+        }
+    }
+}
+
+/// Hides an object from the kinds of rays its visibility excludes, e.g. an invisible light
+/// blocker that only casts shadows, or an area light that does not show up in the camera.
+///
+/// Lights in the scene's light list cannot be hidden from scattered rays, since next-event
+/// estimation weighs its samples against those rays finding the light, so for them only the
+/// camera and shadow flags apply.
+pub struct VisibilityMask {
+    object: Arc<dyn Hittable>,
+    visibility: Visibility,
+}
+
+impl VisibilityMask {
+    pub fn new(object: Arc<dyn Hittable>, visibility: Visibility) -> Self {
+        Self { object, visibility }
+    }
+}
+
+impl Hittable for VisibilityMask {
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        if self.visibility.is_visible_to(ray.kind) {
+            return self.object.hit(ray, ray_t);
+        }
+        if matches!(ray.kind, RayKind::Camera | RayKind::Shadow) {
+            return None;
+        }
+
+        self.object
+            .hit(ray, ray_t)
+            .filter(|hit_record| hit_record.light.is_some())
+    }
+
+    fn bounding_box(&self) -> &Aabb {
+        self.object.bounding_box()
+    }
+}
+
+/// Restricts the lights of the scene's light list that illuminate an object.
+pub struct LightLinked {
+    object: Arc<dyn Hittable>,
+    light_links: Arc<LightLinks>,
+}
+
+impl LightLinked {
+    pub fn new(object: Arc<dyn Hittable>, light_links: LightLinks) -> Self {
+        Self {
+            object,
+            light_links: Arc::new(light_links),
+        }
+    }
+}
+
+impl Hittable for LightLinked {
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        let mut hit_record = self.object.hit(ray, ray_t)?;
+        hit_record.light_links = Some(self.light_links.clone());
+
+        Some(hit_record)
+    }
+
+    fn bounding_box(&self) -> &Aabb {
+        self.object.bounding_box()
+    }
+}
@@ -0,0 +1,361 @@
+use glam::DVec3;
+use std::{f64::consts::PI, fs, path::Path};
+
+/// A goniometric light distribution read from an IES LM-63 photometric data file, giving the
+/// luminous intensity of a fixture in candela by direction. Only type C photometry is supported,
+/// which covers architectural and most other fixtures.
+///
+/// Directions are given in the frame of the fixture, where +Z points to the nadir at a vertical
+/// angle of 0 degrees and +X lies in the plane at a horizontal angle of 0 degrees.
+pub struct IesProfile {
+    vertical_angles: Vec<f64>,
+    horizontal_angles: Vec<f64>,
+    // One row of intensities over the vertical angles for each horizontal angle
+    candela: Vec<Vec<f64>>,
+    max_intensity: f64,
+    average_intensity: f64,
+}
+
+impl IesProfile {
+    // Photometric type for type C goniometers, where the vertical axis is the photometric axis
+    const TYPE_C: f64 = 1.0;
+
+    pub fn new(path: &Path) -> Self {
+        let text = fs::read_to_string(path).expect("IES file not found");
+
+        Self::parse(&text).expect("invalid IES file")
+    }
+
+    /// Parses the contents of an IES file, returning `None` if it is malformed or does not use
+    /// type C photometry.
+    pub fn parse(text: &str) -> Option<Self> {
+        // Skip the keyword lines of the header up to the tilt line, which ends it
+        let mut lines = text.lines();
+        let tilt = lines
+            .by_ref()
+            .map(str::trim)
+            .find_map(|line| line.strip_prefix("TILT="))?
+            .trim();
+
+        let rest = lines.collect::<Vec<_>>().join(" ");
+        let mut numbers = rest
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|token| !token.is_empty())
+            .map(|token| token.parse::<f64>().ok());
+        let mut next = || numbers.next().flatten();
+
+        // Lamp tilt data only matters for fixtures mounted at an angle, so it is skipped
+        if tilt == "INCLUDE" {
+            let _lamp_to_luminaire_geometry = next()?;
+            let tilt_angles = next()? as usize;
+            for _ in 0..2 * tilt_angles {
+                next()?;
+            }
+        }
+
+        let _lamps = next()?;
+        let _lumens_per_lamp = next()?;
+        let multiplier = next()?;
+        let vertical_count = next()? as usize;
+        let horizontal_count = next()? as usize;
+        let photometric_type = next()?;
+        let _units = next()?;
+        let _dimensions = [next()?, next()?, next()?];
+        let ballast_factor = next()?;
+        let _ballast_lamp_factor = next()?;
+        let _input_watts = next()?;
+
+        if photometric_type != Self::TYPE_C || vertical_count == 0 || horizontal_count == 0 {
+            return None;
+        }
+
+        let vertical_angles = (0..vertical_count)
+            .map(|_| next())
+            .collect::<Option<Vec<_>>>()?;
+        let horizontal_angles = (0..horizontal_count)
+            .map(|_| next())
+            .collect::<Option<Vec<_>>>()?;
+        let candela = (0..horizontal_count)
+            .map(|_| {
+                (0..vertical_count)
+                    .map(|_| next().map(|value| (value * multiplier * ballast_factor).max(0.0)))
+                    .collect::<Option<Vec<_>>>()
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        let mut profile = Self {
+            max_intensity: candela
+                .iter()
+                .flatten()
+                .fold(0.0, |max, &value| value.max(max)),
+            vertical_angles,
+            horizontal_angles,
+            candela,
+            average_intensity: 0.0,
+        };
+        profile.average_intensity = profile.integrate() / (4.0 * PI);
+
+        Some(profile)
+    }
+
+    /// The peak intensity in candela.
+    pub fn max_intensity(&self) -> f64 {
+        self.max_intensity
+    }
+
+    /// The intensity averaged over all directions, which is the luminous flux over 4π.
+    pub fn average_intensity(&self) -> f64 {
+        self.average_intensity
+    }
+
+    /// Returns the intensity in candela towards the unit `direction`, interpolated between the
+    /// measured angles.
+    pub fn intensity(&self, direction: DVec3) -> f64 {
+        let vertical = direction.z.clamp(-1.0, 1.0).acos().to_degrees();
+        let horizontal = direction
+            .y
+            .atan2(direction.x)
+            .to_degrees()
+            .rem_euclid(360.0);
+
+        // Nothing is emitted outside of the measured vertical range
+        let (first, last) = (
+            self.vertical_angles[0],
+            self.vertical_angles[self.vertical_angles.len() - 1],
+        );
+        if vertical < first || vertical > last {
+            return 0.0;
+        }
+
+        let horizontal = self.fold_horizontal(horizontal);
+        let (row, row_t) = lerp_index(&self.horizontal_angles, horizontal);
+        let (column, column_t) = lerp_index(&self.vertical_angles, vertical);
+        let at = |row: usize| {
+            let values = &self.candela[row];
+            let next = (column + 1).min(values.len() - 1);
+            values[column] + column_t * (values[next] - values[column])
+        };
+
+        let next_row = (row + 1).min(self.candela.len() - 1);
+        at(row) + row_t * (at(next_row) - at(row))
+    }
+
+    /// Returns the intensity towards `direction` relative to the peak intensity.
+    pub fn relative_intensity(&self, direction: DVec3) -> f64 {
+        if self.max_intensity == 0.0 {
+            return 0.0;
+        }
+
+        self.intensity(direction) / self.max_intensity
+    }
+
+    /// Maps a horizontal angle onto the range covered by the data, which may only hold one
+    /// quadrant or one half of a symmetric fixture.
+    fn fold_horizontal(&self, horizontal: f64) -> f64 {
+        let first = self.horizontal_angles[0];
+        let last = self.horizontal_angles[self.horizontal_angles.len() - 1];
+
+        if self.horizontal_angles.len() == 1 {
+            // Symmetric around the vertical axis
+            first
+        } else if last == 90.0 {
+            let horizontal = if horizontal > 180.0 {
+                360.0 - horizontal
+            } else {
+                horizontal
+            };
+            if horizontal > 90.0 {
+                180.0 - horizontal
+            } else {
+                horizontal
+            }
+        } else if last == 180.0 {
+            if horizontal > 180.0 {
+                360.0 - horizontal
+            } else {
+                horizontal
+            }
+        } else if horizontal < first {
+            horizontal + 360.0
+        } else {
+            horizontal
+        }
+    }
+
+    /// Integrates the intensity over the sphere with the midpoint rule, giving the luminous flux.
+    fn integrate(&self) -> f64 {
+        let (rows, columns) = (90, 180);
+
+        (0..rows * columns)
+            .map(|index| {
+                let theta = PI * ((index / columns) as f64 + 0.5) / rows as f64;
+                let phi = 2.0 * PI * ((index % columns) as f64 + 0.5) / columns as f64;
+                let direction = DVec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                );
+
+                self.intensity(direction) * theta.sin()
+            })
+            .sum::<f64>()
+            * (PI / rows as f64)
+            * (2.0 * PI / columns as f64)
+    }
+}
+
+/// Finds the interval of the sorted `angles` holding `angle`, returning the index of its start
+/// and how far along the interval the angle is.
+fn lerp_index(angles: &[f64], angle: f64) -> (usize, f64) {
+    let index = angles
+        .partition_point(|&value| value <= angle)
+        .clamp(1, angles.len())
+        - 1;
+    if index + 1 >= angles.len() {
+        return (index, 0.0);
+    }
+
+    let width = angles[index + 1] - angles[index];
+    let t = if width > 0.0 {
+        ((angle - angles[index]) / width).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    (index, t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(name: &str) -> IesProfile {
+        IesProfile::new(
+            &Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("assets/ies")
+                .join(name),
+        )
+    }
+
+    /// Returns the direction at the given vertical and horizontal angles in degrees.
+    fn direction(vertical: f64, horizontal: f64) -> DVec3 {
+        let (vertical, horizontal) = (vertical.to_radians(), horizontal.to_radians());
+        DVec3::new(
+            vertical.sin() * horizontal.cos(),
+            vertical.sin() * horizontal.sin(),
+            vertical.cos(),
+        )
+    }
+
+    /// Builds a type C profile covering all vertical angles, whose intensity is 100 plus the
+    /// horizontal angle so that the folded angle can be read back from it.
+    fn synthetic(tilt: &str, horizontal_angles: &[f64], photometric_type: u32) -> String {
+        let rows = horizontal_angles
+            .iter()
+            .map(|angle| format!("{} {}", 100.0 + angle, 100.0 + angle))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let angles = horizontal_angles
+            .iter()
+            .map(|angle| angle.to_string())
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        format!(
+            "IESNA:LM-63-2002\n[TEST] Synthetic\n{tilt}\n1 -1 1 2 {} {photometric_type} 2 0 0 0\n\
+             1.0 1.0 10.0\n0 180\n{angles}\n{rows}\n",
+            horizontal_angles.len()
+        )
+    }
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn parses_rotationally_symmetric_downlight() {
+        let profile = load("downlight.ies");
+
+        assert_eq!(profile.vertical_angles.len(), 19);
+        assert_eq!(profile.horizontal_angles, vec![0.0]);
+        assert_close(profile.max_intensity(), 1500.0, 1e-9);
+        assert_close(profile.intensity(DVec3::Z), 1500.0, 1e-9);
+        assert_close(profile.relative_intensity(DVec3::Z), 1.0, 1e-9);
+        // Halfway between the measurements at 0 and 5 degrees
+        assert_close(profile.intensity(direction(2.5, 0.0)), 1483.05, 1e-9);
+        // A single horizontal angle applies all around the vertical axis
+        assert_close(profile.intensity(direction(2.5, 123.0)), 1483.05, 1e-9);
+        assert_close(profile.intensity(direction(62.5, 0.0)), 4.15, 1e-9);
+        // Nothing is measured above the horizon
+        assert_eq!(profile.intensity(-DVec3::Z), 0.0);
+        // The flux matches the rated lumens of the lamp
+        assert_close(profile.average_intensity() * 4.0 * PI, 1340.0, 20.0);
+    }
+
+    #[test]
+    fn folds_half_plane_wall_washer() {
+        let profile = load("wall_washer.ies");
+
+        assert_eq!(profile.horizontal_angles.len(), 9);
+        assert_close(profile.max_intensity(), 914.8, 1e-9);
+        assert_close(profile.intensity(direction(40.0, 90.0)), 247.4, 1e-9);
+        // Mirrored across the plane through 0 and 180 degrees
+        assert_close(profile.intensity(direction(40.0, 270.0)), 247.4, 1e-9);
+        assert_close(profile.intensity(direction(30.0, 202.5)), 104.0, 1e-9);
+        // Halfway between the planes at 0 and 22.5 degrees
+        assert_close(profile.intensity(direction(10.0, 11.25)), 686.65, 1e-9);
+        assert_close(profile.relative_intensity(direction(30.0, 0.0)), 1.0, 1e-9);
+    }
+
+    #[test]
+    fn folds_horizontal_symmetries() {
+        let single = IesProfile::parse(&synthetic("TILT=NONE", &[0.0], 1)).unwrap();
+        assert_close(single.intensity(direction(90.0, 250.0)), 100.0, 1e-9);
+
+        let quadrant = IesProfile::parse(&synthetic("TILT=NONE", &[0.0, 45.0, 90.0], 1)).unwrap();
+        assert_close(quadrant.intensity(direction(90.0, 30.0)), 130.0, 1e-9);
+        assert_close(quadrant.intensity(direction(90.0, 135.0)), 145.0, 1e-9);
+        assert_close(quadrant.intensity(direction(90.0, 200.0)), 120.0, 1e-9);
+        assert_close(quadrant.intensity(direction(90.0, 300.0)), 160.0, 1e-9);
+
+        let half = IesProfile::parse(&synthetic("TILT=NONE", &[0.0, 90.0, 180.0], 1)).unwrap();
+        assert_close(half.intensity(direction(90.0, 135.0)), 235.0, 1e-9);
+        assert_close(half.intensity(direction(90.0, 270.0)), 190.0, 1e-9);
+
+        let full = IesProfile::parse(&synthetic(
+            "TILT=NONE",
+            &[0.0, 90.0, 180.0, 270.0, 360.0],
+            1,
+        ))
+        .unwrap();
+        assert_close(full.intensity(direction(90.0, 300.0)), 400.0, 1e-9);
+    }
+
+    #[test]
+    fn normalizes_to_peak_and_flux() {
+        let profile = IesProfile::parse(&synthetic("TILT=NONE", &[0.0], 1)).unwrap();
+
+        assert_close(profile.relative_intensity(direction(45.0, 0.0)), 1.0, 1e-9);
+        // A uniform emitter has the same average and peak intensity
+        assert_close(profile.average_intensity(), 100.0, 1e-2);
+    }
+
+    #[test]
+    fn skips_tilt_data() {
+        let tilt = "TILT=INCLUDE\n1\n3\n0 45 90\n1.0 0.9 0.8";
+        let profile = IesProfile::parse(&synthetic(tilt, &[0.0, 90.0, 180.0], 1)).unwrap();
+
+        assert_close(profile.intensity(direction(90.0, 90.0)), 190.0, 1e-9);
+    }
+
+    #[test]
+    fn rejects_unsupported_files() {
+        let no_tilt = synthetic("", &[0.0], 1);
+        assert!(IesProfile::parse(&no_tilt).is_none());
+
+        let type_b = synthetic("TILT=NONE", &[0.0], 2);
+        assert!(IesProfile::parse(&type_b).is_none());
+    }
+}
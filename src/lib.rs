@@ -1,16 +1,31 @@
 pub mod aabb;
+pub mod background;
 pub mod bvh;
 pub mod camera;
 pub mod color;
 pub mod constant_medium;
+pub mod distribution;
+pub mod environment;
 pub mod hittable;
+pub mod ies;
 pub mod interval;
+pub mod light;
+pub mod light_sampler;
 pub mod material;
+pub mod microfacet;
+pub mod onb;
+pub mod panoramic;
 pub mod perlin;
+pub mod principled;
 pub mod ray;
+pub mod realistic;
 pub mod rt_image;
 pub mod scene;
+pub mod sky;
+pub mod stereo;
+pub mod subsurface;
 pub mod texture;
+pub mod thin_film;
 
 use glam::DVec3;
 use nanorand::Rng;
@@ -18,9 +33,11 @@ pub type Point3 = DVec3;
 
 #[inline]
 pub fn random_f64() -> f64 {
+    // Returns a random real in [0,1). Importance sampling and the microfacet samplers rely on
+    // never seeing values outside that range, so all 53 bits of the mantissa are filled.
     let mut rng = nanorand::tls_rng();
 
-    rng.generate_range(0..=1000) as f64 / 1001.0
+    (rng.generate::<u64>() >> 11) as f64 / (1_u64 << 53) as f64
 }
 
 #[inline]
@@ -79,6 +96,19 @@ pub fn random_in_hemisphere(normal: &DVec3) -> DVec3 {
     }
 }
 
+pub fn random_cosine_direction() -> DVec3 {
+    // Returns a cosine-weighted direction in the hemisphere around +Z.
+    let r1 = random_f64();
+    let r2 = random_f64();
+
+    let phi = 2.0 * std::f64::consts::PI * r1;
+    let x = phi.cos() * r2.sqrt();
+    let y = phi.sin() * r2.sqrt();
+    let z = (1.0 - r2).sqrt();
+
+    DVec3::new(x, y, z)
+}
+
 pub fn random_in_unit_disk() -> DVec3 {
     loop {
         let p = DVec3::new(
@@ -0,0 +1,505 @@
+use crate::{
+    aabb::Aabb,
+    color::{luminance, Color},
+    hittable::{HitRecord, Hittable, Quad},
+    ies::IesProfile,
+    interval::Interval,
+    light_sampler::LightBounds,
+    material::Material,
+    onb::Onb,
+    random_f64,
+    ray::Ray,
+    Point3,
+};
+use glam::DVec3;
+use std::{
+    f64::consts::PI,
+    sync::{Arc, Weak},
+};
+
+/// Incident light arriving at a shading point from a light sample.
+pub struct LightSample {
+    /// Unit direction from the shading point towards the light
+    pub direction: DVec3,
+    /// Distance to the sampled point on the light, infinite for distant lights
+    pub distance: f64,
+    /// Incident radiance divided by the density of the sampled direction
+    pub radiance: Color,
+    /// Solid angle density of the sampled direction, zero for lights that rays cannot hit
+    pub pdf: f64,
+}
+
+/// A light sampled by next-event estimation. Most lights are not part of the scene geometry, such
+/// as an infinitesimal point light, and can only be reached this way.
+pub trait Light: Sync + Send {
+    fn sample(&self, point: Point3) -> Option<LightSample>;
+
+    /// Returns the solid angle density with which `sample` generates the unit `direction` from
+    /// `point`, zero for lights that rays cannot hit.
+    fn pdf(&self, _point: Point3, _direction: DVec3) -> f64 {
+        0.0
+    }
+
+    /// Estimates the luminance of the total emitted power, given the radius of a sphere around
+    /// the scene for lights that are infinitely far away.
+    fn power(&self, scene_radius: f64) -> f64;
+
+    /// Bounds where the light is and which way it emits, or `None` if it is infinitely far away.
+    fn bounds(&self) -> Option<LightBounds>;
+}
+
+/// Links an object to a subset of the lights in the scene's light list, either the only lights
+/// that illuminate it or the lights that do not. Emissive objects outside the light list and the
+/// background count as unlisted lights, which only illuminate objects with `Exclude` links.
+/// Links only apply to light arriving directly from the light, not to light bounced off other
+/// objects.
+pub enum LightLinks {
+    Include(Vec<Arc<dyn Light>>),
+    Exclude(Vec<Arc<dyn Light>>),
+}
+
+impl LightLinks {
+    pub fn illuminates(&self, light: &Arc<dyn Light>) -> bool {
+        match self {
+            Self::Include(lights) => lights.iter().any(|linked| Arc::ptr_eq(linked, light)),
+            Self::Exclude(lights) => !lights.iter().any(|linked| Arc::ptr_eq(linked, light)),
+        }
+    }
+
+    /// Returns true if emissive objects outside the light list and the background illuminate
+    /// the object.
+    pub fn illuminates_unlisted(&self) -> bool {
+        matches!(self, Self::Exclude(_))
+    }
+}
+
+/// A photometric profile oriented in world space, with its nadir along `w`.
+struct OrientedProfile {
+    profile: Arc<IesProfile>,
+    onb: Onb,
+}
+
+impl OrientedProfile {
+    /// Returns the intensity relative to the peak for light leaving along `direction`.
+    fn scale(profile: &Option<Self>, direction: DVec3) -> f64 {
+        profile.as_ref().map_or(1.0, |oriented| {
+            oriented
+                .profile
+                .relative_intensity(oriented.onb.to_local(direction))
+        })
+    }
+
+    /// Returns the average intensity relative to the peak, which scales the emitted power.
+    fn average_scale(profile: &Option<Self>) -> f64 {
+        profile.as_ref().map_or(1.0, |oriented| {
+            let max_intensity = oriented.profile.max_intensity();
+            if max_intensity == 0.0 {
+                0.0
+            } else {
+                oriented.profile.average_intensity() / max_intensity
+            }
+        })
+    }
+}
+
+/// A light emitting uniformly in all directions from a point, or from a small sphere when
+/// `radius` is positive. `intensity` is the radiant intensity in W/sr, or the peak intensity
+/// when shaped by a photometric profile.
+pub struct PointLight {
+    position: Point3,
+    intensity: Color,
+    radius: f64,
+    profile: Option<OrientedProfile>,
+}
+
+impl PointLight {
+    pub fn new(position: Point3, intensity: Color) -> Self {
+        Self {
+            position,
+            intensity,
+            radius: 0.0,
+            profile: None,
+        }
+    }
+
+    pub fn with_radius(mut self, radius: f64) -> Self {
+        self.radius = radius.max(0.0);
+        self
+    }
+
+    /// Scales the intensity by a photometric profile relative to its peak, with the nadir of
+    /// the profile pointing along `axis`, usually straight down.
+    pub fn with_profile(mut self, profile: Arc<IesProfile>, axis: DVec3) -> Self {
+        self.profile = Some(OrientedProfile {
+            profile,
+            onb: Onb::from_w(axis),
+        });
+        self
+    }
+}
+
+impl Light for PointLight {
+    fn sample(&self, point: Point3) -> Option<LightSample> {
+        let to_light = self.position - point;
+        let distance_squared = to_light.length_squared();
+        let distance = distance_squared.sqrt();
+        if distance == 0.0 {
+            return None;
+        }
+
+        let intensity =
+            self.intensity * OrientedProfile::scale(&self.profile, -to_light / distance);
+        if self.radius == 0.0 || distance <= self.radius {
+            return Some(LightSample {
+                direction: to_light / distance,
+                distance,
+                radiance: intensity / distance_squared,
+                pdf: 0.0,
+            });
+        }
+
+        // Sample the cone of directions subtended by the sphere, whose radiance is chosen so that
+        // it matches the intensity of the point light from far away
+        let cos_theta_max = (1.0 - self.radius * self.radius / distance_squared).sqrt();
+        let direction = Onb::from_w(to_light).local(random_to_cone(cos_theta_max));
+        let radiance = intensity / (PI * self.radius * self.radius);
+        let solid_angle = 2.0 * PI * (1.0 - cos_theta_max);
+
+        // Distance to the near side of the sphere along the sampled direction
+        let b = direction.dot(to_light);
+        let c = distance_squared - self.radius * self.radius;
+        let surface_distance = b - (b * b - c).max(0.0).sqrt();
+
+        Some(LightSample {
+            direction,
+            distance: surface_distance,
+            radiance: radiance * solid_angle,
+            pdf: 0.0,
+        })
+    }
+
+    fn power(&self, _scene_radius: f64) -> f64 {
+        4.0 * PI * luminance(self.intensity) * OrientedProfile::average_scale(&self.profile)
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        let extent = DVec3::splat(self.radius);
+
+        Some(LightBounds::new(
+            Aabb::from_points(&(self.position - extent), &(self.position + extent)),
+            DVec3::Y,
+            4.0 * PI * luminance(self.intensity) * OrientedProfile::average_scale(&self.profile),
+            -1.0,
+            0.0,
+            false,
+        ))
+    }
+}
+
+/// A point light restricted to a cone around `direction`. The intensity is constant within
+/// `falloff_start` degrees of the axis and fades out smoothly until `total_width` degrees.
+pub struct SpotLight {
+    position: Point3,
+    direction: DVec3,
+    intensity: Color,
+    cos_falloff_start: f64,
+    cos_total_width: f64,
+    profile: Option<OrientedProfile>,
+}
+
+impl SpotLight {
+    pub fn new(
+        position: Point3,
+        direction: DVec3,
+        intensity: Color,
+        total_width: f64,
+        falloff_start: f64,
+    ) -> Self {
+        Self {
+            position,
+            direction: direction.normalize(),
+            intensity,
+            cos_falloff_start: falloff_start.min(total_width).to_radians().cos(),
+            cos_total_width: total_width.to_radians().cos(),
+            profile: None,
+        }
+    }
+
+    /// Scales the intensity by a photometric profile relative to its peak, with the nadir of
+    /// the profile along the axis of the spot. The cone still applies on top, so give it a
+    /// total width of 180 degrees to use the profile alone.
+    pub fn with_profile(mut self, profile: Arc<IesProfile>) -> Self {
+        self.profile = Some(OrientedProfile {
+            profile,
+            onb: Onb::from_w(self.direction),
+        });
+        self
+    }
+
+    fn falloff(&self, cos_theta: f64) -> f64 {
+        if cos_theta >= self.cos_falloff_start {
+            return 1.0;
+        }
+        if cos_theta <= self.cos_total_width {
+            return 0.0;
+        }
+
+        let t =
+            (cos_theta - self.cos_total_width) / (self.cos_falloff_start - self.cos_total_width);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+impl Light for SpotLight {
+    fn sample(&self, point: Point3) -> Option<LightSample> {
+        let to_light = self.position - point;
+        let distance_squared = to_light.length_squared();
+        let distance = distance_squared.sqrt();
+        if distance == 0.0 {
+            return None;
+        }
+
+        let direction = to_light / distance;
+        let falloff = self.falloff(-direction.dot(self.direction));
+        if falloff == 0.0 {
+            return None;
+        }
+
+        Some(LightSample {
+            direction,
+            distance,
+            radiance: self.intensity * falloff * OrientedProfile::scale(&self.profile, -direction)
+                / distance_squared,
+            pdf: 0.0,
+        })
+    }
+
+    fn power(&self, _scene_radius: f64) -> f64 {
+        // Integral of the falloff over the sphere, approximating the smoothstep as linear
+        luminance(self.intensity)
+            * 2.0
+            * PI
+            * (1.0 - (self.cos_falloff_start + self.cos_total_width) / 2.0)
+            * OrientedProfile::average_scale(&self.profile)
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        let theta_o = self.cos_falloff_start.acos();
+        let theta_e = self.cos_total_width.acos() - theta_o;
+
+        // As for point lights, the cone bounds take care of where the light is not emitted, and
+        // the profile scales the light emitted within them as it does the power
+        Some(LightBounds::new(
+            Aabb::from_points(&self.position, &self.position),
+            self.direction,
+            4.0 * PI * luminance(self.intensity) * OrientedProfile::average_scale(&self.profile),
+            self.cos_falloff_start,
+            theta_e.cos(),
+            false,
+        ))
+    }
+}
+
+/// A light infinitely far away, such as the sun, shining along `direction`. `irradiance` is the
+/// irradiance in W/m^2 on a surface facing the light, and a positive `angular_diameter` in degrees
+/// gives soft shadows.
+pub struct DirectionalLight {
+    direction: DVec3,
+    irradiance: Color,
+    cos_theta_max: f64,
+}
+
+impl DirectionalLight {
+    pub fn new(direction: DVec3, irradiance: Color) -> Self {
+        Self {
+            direction: direction.normalize(),
+            irradiance,
+            cos_theta_max: 1.0,
+        }
+    }
+
+    pub fn with_angular_diameter(mut self, angular_diameter: f64) -> Self {
+        self.cos_theta_max = (angular_diameter / 2.0).to_radians().cos();
+        self
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample(&self, _point: Point3) -> Option<LightSample> {
+        let direction = if self.cos_theta_max < 1.0 {
+            Onb::from_w(-self.direction).local(random_to_cone(self.cos_theta_max))
+        } else {
+            -self.direction
+        };
+
+        // The radiance of the disk times its solid angle, which is also the inverse of the
+        // sampling density, gives back the irradiance
+        Some(LightSample {
+            direction,
+            distance: f64::INFINITY,
+            radiance: self.irradiance,
+            pdf: 0.0,
+        })
+    }
+
+    fn power(&self, scene_radius: f64) -> f64 {
+        // Everything that falls on a disk as large as the scene
+        PI * scene_radius * scene_radius * luminance(self.irradiance)
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        None
+    }
+}
+
+/// A parallelogram that emits light through its material, like a `Quad` with a `DiffuseLight`,
+/// and is also sampled by next-event estimation. Add it both to the world and to the scene's
+/// light list; it must not be moved by `Translate` or `RotationY`, which the light samples would
+/// not follow.
+pub struct QuadLight {
+    quad: Quad,
+    q: Point3,
+    u: DVec3,
+    v: DVec3,
+    normal: DVec3,
+    area: f64,
+    // Handed out in hit records so that the scene can tell which light a ray found
+    this: Weak<QuadLight>,
+}
+
+impl QuadLight {
+    pub fn new(q: Point3, u: DVec3, v: DVec3, material: Arc<dyn Material>) -> Arc<Self> {
+        let n = u.cross(v);
+
+        Arc::new_cyclic(|this| Self {
+            quad: Quad::new(q, u, v, material),
+            q,
+            u,
+            v,
+            normal: n.normalize(),
+            area: n.length(),
+            this: this.clone(),
+        })
+    }
+
+    /// Returns the radiance emitted at the center of the quad towards the side the normal
+    /// points to, or the back side, averaged over the hemisphere with cosine weights. Focused
+    /// emitters and photometric profiles make this smaller than the radiance along the normal.
+    fn center_radiance(&self, back: bool) -> Color {
+        let (rows, columns) = (32, 64);
+        let center = self.q + 0.5 * (self.u + self.v);
+        let onb = Onb::from_w_and_tangent(if back { -self.normal } else { self.normal }, self.u);
+
+        // Integrate the radiance times cosine with the midpoint rule and divide by pi
+        (0..rows * columns)
+            .map(|index| {
+                let theta = 0.5 * PI * ((index / columns) as f64 + 0.5) / rows as f64;
+                let phi = 2.0 * PI * ((index % columns) as f64 + 0.5) / columns as f64;
+                let direction = onb.local(DVec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                ));
+                let ray = Ray::new(center + direction, -direction, 0.0);
+
+                self.quad
+                    .hit(&ray, Interval::new(0.0, f64::INFINITY))
+                    .map_or(Color::ZERO, |hit_record| {
+                        hit_record.material.emitted(&ray, &hit_record)
+                    })
+                    * theta.cos()
+                    * theta.sin()
+            })
+            .fold(Color::ZERO, |sum, radiance| sum + radiance)
+            * (0.5 * PI / rows as f64)
+            * (2.0 * PI / columns as f64)
+            / PI
+    }
+}
+
+impl Light for QuadLight {
+    fn sample(&self, point: Point3) -> Option<LightSample> {
+        let target = self.q + random_f64() * self.u + random_f64() * self.v;
+        let to_light = target - point;
+        let distance = to_light.length();
+        if distance == 0.0 {
+            return None;
+        }
+
+        let direction = to_light / distance;
+        let ray = Ray::new(point, direction, 0.0);
+        let hit_record = self.quad.hit(&ray, Interval::new(0.0, f64::INFINITY))?;
+        let pdf = self.pdf(point, direction);
+        if pdf == 0.0 {
+            return None;
+        }
+
+        Some(LightSample {
+            direction,
+            distance: hit_record.t,
+            radiance: hit_record.material.emitted(&ray, &hit_record) / pdf,
+            pdf,
+        })
+    }
+
+    fn pdf(&self, point: Point3, direction: DVec3) -> f64 {
+        let ray = Ray::new(point, direction, 0.0);
+        let Some(hit_record) = self.quad.hit(&ray, Interval::new(0.0, f64::INFINITY)) else {
+            return 0.0;
+        };
+
+        // Convert the uniform density over the area to solid angle
+        let cos_theta = direction.dot(self.normal).abs();
+        if cos_theta < 1e-8 {
+            return 0.0;
+        }
+        hit_record.t * hit_record.t / (cos_theta * self.area)
+    }
+
+    fn power(&self, _scene_radius: f64) -> f64 {
+        let radiance =
+            luminance(self.center_radiance(false)) + luminance(self.center_radiance(true));
+        PI * self.area * radiance
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        let front = luminance(self.center_radiance(false));
+        let back = luminance(self.center_radiance(true));
+
+        Some(LightBounds::new(
+            self.quad.bounding_box().clone(),
+            if front > 0.0 {
+                self.normal
+            } else {
+                -self.normal
+            },
+            front.max(back) * self.area,
+            1.0,
+            0.0,
+            front > 0.0 && back > 0.0,
+        ))
+    }
+}
+
+impl Hittable for QuadLight {
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        let mut hit_record = self.quad.hit(ray, ray_t)?;
+        hit_record.light = self.this.upgrade().map(|light| light as Arc<dyn Light>);
+
+        Some(hit_record)
+    }
+
+    fn bounding_box(&self) -> &Aabb {
+        self.quad.bounding_box()
+    }
+}
+
+/// Returns a direction uniformly distributed in the cone around +Z with the given cosine of its
+/// half angle.
+fn random_to_cone(cos_theta_max: f64) -> DVec3 {
+    let cos_theta = 1.0 - random_f64() * (1.0 - cos_theta_max);
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * random_f64();
+
+    DVec3::new(phi.cos() * sin_theta, phi.sin() * sin_theta, cos_theta)
+}
@@ -0,0 +1,513 @@
+use crate::{aabb::Aabb, distribution::AliasTable, light::Light, Point3};
+use glam::{DQuat, DVec3};
+use std::{f64::consts::PI, sync::Arc};
+
+// Largest f64 below one, to keep remapped random numbers in [0, 1)
+const ONE_MINUS_EPSILON: f64 = 1.0 - f64::EPSILON / 2.0;
+
+/// How next-event estimation picks the one light it samples at each shading point.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum LightSampling {
+    /// In proportion to the power of each light, wherever the shading point is
+    Power,
+    /// By the estimated contribution of each light to the shading point, using a light BVH
+    #[default]
+    Bvh,
+}
+
+impl LightSampling {
+    pub fn build(self, lights: &[Arc<dyn Light>], scene_radius: f64) -> Box<dyn LightSampler> {
+        match self {
+            Self::Power => Box::new(PowerLightSampler::new(lights, scene_radius)),
+            Self::Bvh => Box::new(BvhLightSampler::new(lights)),
+        }
+    }
+}
+
+/// Chooses lights from the scene's light list, by index, for a shading point with the given
+/// normal. The normal is zero for points inside participating media.
+pub trait LightSampler: Sync + Send {
+    /// Maps a uniform random number to a light, returning its index and probability.
+    fn sample(&self, point: Point3, normal: DVec3, u: f64) -> Option<(usize, f64)>;
+
+    /// Returns the probability that `sample` picks the light at `index`.
+    fn pmf(&self, point: Point3, normal: DVec3, index: usize) -> f64;
+}
+
+/// Picks lights in proportion to their power from an alias table.
+pub struct PowerLightSampler {
+    table: AliasTable,
+}
+
+impl PowerLightSampler {
+    pub fn new(lights: &[Arc<dyn Light>], scene_radius: f64) -> Self {
+        let powers = lights
+            .iter()
+            .map(|light| light.power(scene_radius))
+            .collect::<Vec<_>>();
+
+        Self {
+            table: AliasTable::new(&powers),
+        }
+    }
+}
+
+impl LightSampler for PowerLightSampler {
+    fn sample(&self, _point: Point3, _normal: DVec3, u: f64) -> Option<(usize, f64)> {
+        if self.table.count() == 0 {
+            return None;
+        }
+
+        Some(self.table.sample(u))
+    }
+
+    fn pmf(&self, _point: Point3, _normal: DVec3, index: usize) -> f64 {
+        self.table.pmf(index)
+    }
+}
+
+/// Bounds the emission of a light, or of a group of lights, for the light BVH: where it is, how
+/// much power it emits and the cone of directions it emits into.
+#[derive(Clone)]
+pub struct LightBounds {
+    bounds: Aabb,
+    direction: DVec3,
+    phi: f64,
+    cos_theta_o: f64,
+    cos_theta_e: f64,
+    two_sided: bool,
+}
+
+impl LightBounds {
+    /// Every surface normal of the light lies within `theta_o` of `direction`, and light leaves
+    /// the surface at most `theta_e` beyond those normals. `phi` is the luminance of the emitted
+    /// power. Two-sided lights also emit around `-direction`.
+    pub fn new(
+        bounds: Aabb,
+        direction: DVec3,
+        phi: f64,
+        cos_theta_o: f64,
+        cos_theta_e: f64,
+        two_sided: bool,
+    ) -> Self {
+        Self {
+            bounds,
+            direction: direction.normalize(),
+            phi,
+            cos_theta_o,
+            cos_theta_e,
+            two_sided,
+        }
+    }
+
+    pub fn union(&self, other: &LightBounds) -> Self {
+        if self.phi == 0.0 {
+            return other.clone();
+        }
+        if other.phi == 0.0 {
+            return self.clone();
+        }
+
+        let (direction, cos_theta_o) = union_cones(
+            (self.direction, self.cos_theta_o),
+            (other.direction, other.cos_theta_o),
+        );
+
+        Self {
+            bounds: Aabb::from_aabbs(&self.bounds, &other.bounds),
+            direction,
+            phi: self.phi + other.phi,
+            cos_theta_o,
+            cos_theta_e: self.cos_theta_e.min(other.cos_theta_e),
+            two_sided: self.two_sided || other.two_sided,
+        }
+    }
+
+    /// Estimates how much the bounded lights contribute to a point with the given normal,
+    /// conservatively taking the most favourable position and orientation within the bounds.
+    pub fn importance(&self, point: Point3, normal: DVec3) -> f64 {
+        let center = self.bounds.center();
+        let radius = self.bounds.diagonal().length() / 2.0;
+        let distance_squared = point.distance_squared(center);
+        let d2 = distance_squared.max(radius);
+
+        // Angle between the emission axis and the way towards the point
+        let wi = (point - center).normalize_or_zero();
+        let mut cos_theta_w = self.direction.dot(wi);
+        if self.two_sided {
+            cos_theta_w = cos_theta_w.abs();
+        }
+        let sin_theta_w = safe_sqrt(1.0 - cos_theta_w * cos_theta_w);
+
+        // Half angle of the cone of directions from the point that covers the bounds
+        let cos_theta_b = if distance_squared < radius * radius {
+            -1.0
+        } else {
+            safe_sqrt(1.0 - radius * radius / distance_squared)
+        };
+        let sin_theta_b = safe_sqrt(1.0 - cos_theta_b * cos_theta_b);
+
+        // Smallest angle to the point from within the cone of normals, then the bounds
+        let sin_theta_o = safe_sqrt(1.0 - self.cos_theta_o * self.cos_theta_o);
+        let cos_theta_x = cos_sub_clamped(sin_theta_w, cos_theta_w, sin_theta_o, self.cos_theta_o);
+        let sin_theta_x = sin_sub_clamped(sin_theta_w, cos_theta_w, sin_theta_o, self.cos_theta_o);
+        let cos_theta_p = cos_sub_clamped(sin_theta_x, cos_theta_x, sin_theta_b, cos_theta_b);
+        if cos_theta_p <= self.cos_theta_e {
+            return 0.0;
+        }
+
+        let mut importance = self.phi * cos_theta_p / d2;
+
+        // Smallest angle of incidence at the point, either side of the surface
+        if normal != DVec3::ZERO {
+            let cos_theta_i = wi.dot(normal).abs();
+            let sin_theta_i = safe_sqrt(1.0 - cos_theta_i * cos_theta_i);
+            importance *= cos_sub_clamped(sin_theta_i, cos_theta_i, sin_theta_b, cos_theta_b);
+        }
+
+        importance.max(0.0)
+    }
+
+    /// The surface area orientation heuristic cost of these bounds as part of a split of a node
+    /// with the given extent along `axis`.
+    fn cost(&self, extent: &Aabb, axis: usize) -> f64 {
+        let theta_o = self.cos_theta_o.clamp(-1.0, 1.0).acos();
+        let theta_e = self.cos_theta_e.clamp(-1.0, 1.0).acos();
+        let theta_w = (theta_o + theta_e).min(PI);
+        let sin_theta_o = safe_sqrt(1.0 - self.cos_theta_o * self.cos_theta_o);
+
+        // Solid angle measure of the emitted directions, weighted by their cosine
+        let m_omega = 2.0 * PI * (1.0 - self.cos_theta_o)
+            + PI / 2.0
+                * (2.0 * theta_w * sin_theta_o
+                    - (theta_o - 2.0 * theta_w).cos()
+                    - 2.0 * theta_o * sin_theta_o
+                    + self.cos_theta_o);
+
+        // Discourage thin slabs across the longest axis
+        let diagonal = extent.diagonal();
+        let k_r = diagonal.max_element() / diagonal[axis];
+
+        self.phi * m_omega * k_r * self.bounds.surface_area()
+    }
+}
+
+/// Picks lights by walking down a BVH over their bounds, choosing each child in proportion to its
+/// estimated importance to the shading point (Conty Estevez and Kulla 2018). Lights infinitely
+/// far away are kept outside the tree and picked uniformly.
+pub struct BvhLightSampler {
+    nodes: Vec<LightNode>,
+    infinite_lights: Vec<usize>,
+    // Path from the root to the leaf of each light, one bit per level set for the second child
+    bit_trails: Vec<Option<u64>>,
+}
+
+struct LightNode {
+    bounds: LightBounds,
+    kind: LightNodeKind,
+}
+
+enum LightNodeKind {
+    Leaf(usize),
+    // The first child directly follows its parent
+    Interior { second_child: usize },
+}
+
+impl BvhLightSampler {
+    const BUCKETS: usize = 12;
+    // Deeper than this, nodes are split in the middle so that the bit trails cannot overflow
+    const MAX_HEURISTIC_DEPTH: u32 = 32;
+
+    pub fn new(lights: &[Arc<dyn Light>]) -> Self {
+        let mut sampler = Self {
+            nodes: Vec::new(),
+            infinite_lights: Vec::new(),
+            bit_trails: vec![None; lights.len()],
+        };
+
+        let mut bounded_lights = Vec::new();
+        for (index, light) in lights.iter().enumerate() {
+            match light.bounds() {
+                Some(bounds) if bounds.phi > 0.0 => bounded_lights.push((index, bounds)),
+                Some(_) => {}
+                None => sampler.infinite_lights.push(index),
+            }
+        }
+
+        if !bounded_lights.is_empty() {
+            sampler.build(&mut bounded_lights, 0, 0);
+        }
+
+        sampler
+    }
+
+    fn build(&mut self, lights: &mut [(usize, LightBounds)], bit_trail: u64, depth: u32) -> usize {
+        if let [(light, bounds)] = lights {
+            self.bit_trails[*light] = Some(bit_trail);
+            self.nodes.push(LightNode {
+                bounds: bounds.clone(),
+                kind: LightNodeKind::Leaf(*light),
+            });
+            return self.nodes.len() - 1;
+        }
+
+        let bounds = lights[1..]
+            .iter()
+            .fold(lights[0].1.clone(), |union, (_, bounds)| {
+                union.union(bounds)
+            });
+        let mid = self.split(lights, &bounds, depth);
+
+        // Reserve the parent's slot so that the first child lands right after it
+        let index = self.nodes.len();
+        self.nodes.push(LightNode {
+            bounds: bounds.clone(),
+            kind: LightNodeKind::Interior { second_child: 0 },
+        });
+        self.build(&mut lights[..mid], bit_trail, depth + 1);
+        let second_child = self.build(&mut lights[mid..], bit_trail | (1 << depth), depth + 1);
+        self.nodes[index].kind = LightNodeKind::Interior { second_child };
+
+        index
+    }
+
+    /// Reorders the lights into the two halves of the cheapest split and returns where the
+    /// second half starts.
+    fn split(
+        &self,
+        lights: &mut [(usize, LightBounds)],
+        bounds: &LightBounds,
+        depth: u32,
+    ) -> usize {
+        let centroid_bounds = lights[1..].iter().fold(
+            Aabb::from_points(&lights[0].1.bounds.center(), &lights[0].1.bounds.center()),
+            |union, (_, bounds)| {
+                let center = bounds.bounds.center();
+                Aabb::from_aabbs(&union, &Aabb::from_points(&center, &center))
+            },
+        );
+        let bucket = |axis: usize, bounds: &LightBounds| {
+            let interval = centroid_bounds.axis(axis);
+            let offset = (bounds.bounds.center()[axis] - interval.min) / interval.size();
+            ((Self::BUCKETS as f64 * offset) as usize).min(Self::BUCKETS - 1)
+        };
+
+        let mut best: Option<(f64, usize, usize)> = None;
+        for axis in 0..3 {
+            if depth >= Self::MAX_HEURISTIC_DEPTH || centroid_bounds.axis(axis).size() <= 0.0 {
+                continue;
+            }
+
+            let mut buckets: [Option<LightBounds>; Self::BUCKETS] = Default::default();
+            for (_, light_bounds) in lights.iter() {
+                let bucket = &mut buckets[bucket(axis, light_bounds)];
+                *bucket = Some(match bucket {
+                    Some(union) => union.union(light_bounds),
+                    None => light_bounds.clone(),
+                });
+            }
+
+            for split in 0..Self::BUCKETS - 1 {
+                let union = |buckets: &[Option<LightBounds>]| {
+                    buckets.iter().flatten().fold(None, |union, bounds| {
+                        Some(match union {
+                            Some(union) => bounds.union(&union),
+                            None => bounds.clone(),
+                        })
+                    })
+                };
+                let cost = [union(&buckets[..=split]), union(&buckets[split + 1..])]
+                    .iter()
+                    .flatten()
+                    .map(|half| half.cost(&bounds.bounds, axis))
+                    .sum::<f64>();
+
+                if cost > 0.0 && best.is_none_or(|(best_cost, _, _)| cost < best_cost) {
+                    best = Some((cost, axis, split));
+                }
+            }
+        }
+
+        match best {
+            Some((_, axis, split)) => {
+                lights.sort_by_key(|(_, light_bounds)| bucket(axis, light_bounds) > split);
+                lights
+                    .iter()
+                    .position(|(_, light_bounds)| bucket(axis, light_bounds) > split)
+                    .unwrap_or(lights.len() / 2)
+            }
+            None => {
+                // Fall back to splitting evenly along the longest axis
+                let diagonal = centroid_bounds.diagonal();
+                let axis = (0..3)
+                    .max_by(|&a, &b| diagonal[a].total_cmp(&diagonal[b]))
+                    .unwrap_or(0);
+                let mid = lights.len() / 2;
+                lights.select_nth_unstable_by(mid, |(_, a), (_, b)| {
+                    a.bounds.center()[axis].total_cmp(&b.bounds.center()[axis])
+                });
+                mid
+            }
+        }
+    }
+
+    fn infinite_probability(&self) -> f64 {
+        let infinite = self.infinite_lights.len() as f64;
+        let tree = if self.nodes.is_empty() { 0.0 } else { 1.0 };
+
+        if infinite == 0.0 {
+            0.0
+        } else {
+            infinite / (infinite + tree)
+        }
+    }
+
+    /// Returns the probability of descending into the first child of an interior node, or
+    /// `None` if neither child contributes.
+    fn first_child_probability(
+        &self,
+        index: usize,
+        second_child: usize,
+        point: Point3,
+        normal: DVec3,
+    ) -> Option<f64> {
+        let first = self.nodes[index + 1].bounds.importance(point, normal);
+        let second = self.nodes[second_child].bounds.importance(point, normal);
+        if first + second == 0.0 {
+            return None;
+        }
+
+        Some(first / (first + second))
+    }
+}
+
+impl LightSampler for BvhLightSampler {
+    fn sample(&self, point: Point3, normal: DVec3, u: f64) -> Option<(usize, f64)> {
+        let p_infinite = self.infinite_probability();
+        if u < p_infinite {
+            let count = self.infinite_lights.len();
+            let index = ((u / p_infinite * count as f64) as usize).min(count - 1);
+            return Some((self.infinite_lights[index], p_infinite / count as f64));
+        }
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let mut u = ((u - p_infinite) / (1.0 - p_infinite)).min(ONE_MINUS_EPSILON);
+        let mut pmf = 1.0 - p_infinite;
+        let mut index = 0;
+        loop {
+            let node = &self.nodes[index];
+            match node.kind {
+                LightNodeKind::Leaf(light) => {
+                    // A lone light has not been checked against the point yet
+                    if index == 0 && node.bounds.importance(point, normal) == 0.0 {
+                        return None;
+                    }
+                    return Some((light, pmf));
+                }
+                LightNodeKind::Interior { second_child } => {
+                    let p_first =
+                        self.first_child_probability(index, second_child, point, normal)?;
+                    if u < p_first {
+                        index += 1;
+                        pmf *= p_first;
+                        u = (u / p_first).min(ONE_MINUS_EPSILON);
+                    } else {
+                        index = second_child;
+                        pmf *= 1.0 - p_first;
+                        u = ((u - p_first) / (1.0 - p_first)).min(ONE_MINUS_EPSILON);
+                    }
+                }
+            }
+        }
+    }
+
+    fn pmf(&self, point: Point3, normal: DVec3, index: usize) -> f64 {
+        let p_infinite = self.infinite_probability();
+        let Some(mut bit_trail) = self.bit_trails[index] else {
+            return if self.infinite_lights.contains(&index) {
+                p_infinite / self.infinite_lights.len() as f64
+            } else {
+                0.0
+            };
+        };
+
+        // Follow the light's path down the tree
+        let mut pmf = 1.0 - p_infinite;
+        let mut node_index = 0;
+        loop {
+            let node = &self.nodes[node_index];
+            match node.kind {
+                LightNodeKind::Leaf(_) => {
+                    if node_index == 0 && node.bounds.importance(point, normal) == 0.0 {
+                        return 0.0;
+                    }
+                    return pmf;
+                }
+                LightNodeKind::Interior { second_child } => {
+                    let Some(p_first) =
+                        self.first_child_probability(node_index, second_child, point, normal)
+                    else {
+                        return 0.0;
+                    };
+                    if bit_trail & 1 == 0 {
+                        node_index += 1;
+                        pmf *= p_first;
+                    } else {
+                        node_index = second_child;
+                        pmf *= 1.0 - p_first;
+                    }
+                    bit_trail >>= 1;
+                }
+            }
+        }
+    }
+}
+
+/// Returns the axis and cosine of the half angle of the smallest cone holding both cones.
+fn union_cones(a: (DVec3, f64), b: (DVec3, f64)) -> (DVec3, f64) {
+    let theta_a = a.1.clamp(-1.0, 1.0).acos();
+    let theta_b = b.1.clamp(-1.0, 1.0).acos();
+    let theta_d = a.0.angle_between(b.0);
+
+    // One cone may already hold the other
+    if (theta_d + theta_b).min(PI) <= theta_a {
+        return a;
+    }
+    if (theta_d + theta_a).min(PI) <= theta_b {
+        return b;
+    }
+
+    let theta_o = (theta_a + theta_d + theta_b) / 2.0;
+    let axis = a.0.cross(b.0);
+    if theta_o >= PI || axis.length_squared() == 0.0 {
+        return (a.0, -1.0);
+    }
+
+    // Rotate the axis of the first cone towards the second
+    let rotation = DQuat::from_axis_angle(axis.normalize(), theta_o - theta_a);
+    (rotation * a.0, theta_o.cos())
+}
+
+/// The cosine of the angle a minus the angle b, or one if that is negative.
+fn cos_sub_clamped(sin_a: f64, cos_a: f64, sin_b: f64, cos_b: f64) -> f64 {
+    if cos_a > cos_b {
+        1.0
+    } else {
+        cos_a * cos_b + sin_a * sin_b
+    }
+}
+
+/// The sine of the angle a minus the angle b, or zero if that is negative.
+fn sin_sub_clamped(sin_a: f64, cos_a: f64, sin_b: f64, cos_b: f64) -> f64 {
+    if cos_a > cos_b {
+        0.0
+    } else {
+        sin_a * cos_b - cos_a * sin_b
+    }
+}
+
+fn safe_sqrt(x: f64) -> f64 {
+    x.max(0.0).sqrt()
+}
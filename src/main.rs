@@ -1,13 +1,16 @@
 use glam::DVec3;
 use ray_tracer::{
+    background::SolidBackground,
     bvh::Bvh,
+    camera::ThinLensCamera,
     color::Color,
     constant_medium::ConstantMedium,
     hittable::{create_box, HittableList, MovingSphere, Quad, RotationY, Sphere, Translate},
-    material::Material,
+    light::QuadLight,
+    material::{Dielectric, DiffuseLight, Lambertian, Metal},
     random_f64, random_f64_range,
     scene::Scene,
-    texture::{CheckerTexture, ImageTexture, NoiseTexture, SolidColor},
+    texture::{CheckerTexture, ImageTexture, NoiseTexture},
     Point3,
 };
 use std::sync::Arc;
@@ -23,17 +26,17 @@ fn final_scene(scene: &mut Scene) {
     scene.set_image_width(800);
     scene.set_aspect_ratio(1.0);
     scene.samples_per_pixel = 10000;
-    scene.background_color = Color::new(0.0, 0.0, 0.0);
+    scene.background = Arc::new(SolidBackground::new(Color::new(0.0, 0.0, 0.0)));
 
-    scene.camera.aperture = 0.0;
-    scene.camera.vfov = 40.0;
-    scene.camera.look_from = Point3::new(478.0, 278.0, -600.0);
-    scene.camera.look_at = Point3::new(278.0, 278.0, 0.0);
+    let mut camera = ThinLensCamera::default();
+    camera.aperture = 0.0;
+    camera.vfov = 40.0;
+    camera.look_from = Point3::new(478.0, 278.0, -600.0);
+    camera.look_at = Point3::new(278.0, 278.0, 0.0);
+    scene.camera = Box::new(camera);
 
     let mut boxes_1 = HittableList::default();
-    let ground = Arc::new(Material::Lambertian {
-        albedo: Arc::new(SolidColor::new(Color::new(0.48, 0.84, 0.53))),
-    });
+    let ground = Arc::new(Lambertian::from_color(Color::new(0.48, 0.84, 0.53)));
 
     let boxes_per_side = 20;
     for i in 0..boxes_per_side {
@@ -58,21 +61,18 @@ fn final_scene(scene: &mut Scene) {
 
     world.add(Arc::new(Bvh::from_list(boxes_1)));
 
-    let light = Arc::new(Material::DiffuseLight {
-        emit: Arc::new(SolidColor::new(Color::new(7.0, 7.0, 7.0))),
-    });
-    world.add(Arc::new(Quad::new(
+    let light = QuadLight::new(
         Point3::new(123.0, 554.0, 147.0),
         DVec3::new(300.0, 0.0, 0.0),
         DVec3::new(0.0, 0.0, 265.0),
-        light.clone(),
-    )));
+        Arc::new(DiffuseLight::from_color(Color::new(7.0, 7.0, 7.0))),
+    );
+    world.add(light.clone());
+    scene.lights.push(light);
 
     let center_1 = Point3::new(400.0, 400.0, 200.0);
     let center_2 = center_1 + DVec3::new(30.0, 0.0, 0.0);
-    let moving_sphere_material = Arc::new(Material::Lambertian {
-        albedo: Arc::new(SolidColor::new(Color::new(0.7, 0.3, 0.1))),
-    });
+    let moving_sphere_material = Arc::new(Lambertian::from_color(Color::new(0.7, 0.3, 0.1)));
     world.add(Arc::new(MovingSphere::new(
         center_1,
         center_2,
@@ -83,25 +83,18 @@ fn final_scene(scene: &mut Scene) {
     world.add(Arc::new(Sphere::new(
         Point3::new(260.0, 150.0, 45.0),
         50.0,
-        Arc::new(Material::Dielectric {
-            index_of_refraction: 1.5,
-        }),
+        Arc::new(Dielectric::new(1.5)),
     )));
     world.add(Arc::new(Sphere::new(
         Point3::new(0.0, 150.0, 145.0),
         50.0,
-        Arc::new(Material::Metal {
-            albedo: Color::new(0.8, 0.8, 0.8),
-            fuzz: 1.0,
-        }),
+        Arc::new(Metal::new(Color::new(0.8, 0.8, 0.8), 1.0)),
     )));
 
     let boundary_1 = Arc::new(Sphere::new(
         Point3::new(360.0, 150.0, 145.0),
         70.0,
-        Arc::new(Material::Dielectric {
-            index_of_refraction: 1.5,
-        }),
+        Arc::new(Dielectric::new(1.5)),
     ));
     world.add(boundary_1.clone());
     world.add(Arc::new(ConstantMedium::from_color(
@@ -112,9 +105,7 @@ fn final_scene(scene: &mut Scene) {
     let boundary_2 = Arc::new(Sphere::new(
         Point3::new(0.0, 0.0, 0.0),
         5000.0,
-        Arc::new(Material::Dielectric {
-            index_of_refraction: 1.5,
-        }),
+        Arc::new(Dielectric::new(1.5)),
     ));
     world.add(Arc::new(ConstantMedium::from_color(
         boundary_2.clone(),
@@ -127,9 +118,7 @@ fn final_scene(scene: &mut Scene) {
             .join("assets/images/earthmap.jpg")
             .as_path(),
     ));
-    let earth_material = Arc::new(Material::Lambertian {
-        albedo: earth_texture.clone(),
-    });
+    let earth_material = Arc::new(Lambertian::new(earth_texture.clone()));
     world.add(Arc::new(Sphere::new(
         Point3::new(400.0, 200.0, 400.0),
         100.0,
@@ -137,9 +126,7 @@ fn final_scene(scene: &mut Scene) {
     )));
 
     let perlin_texture = Arc::new(NoiseTexture::new(0.1));
-    let perlin_material = Arc::new(Material::Lambertian {
-        albedo: perlin_texture.clone(),
-    });
+    let perlin_material = Arc::new(Lambertian::new(perlin_texture.clone()));
     world.add(Arc::new(Sphere::new(
         Point3::new(220.0, 280.0, 300.0),
         80.0,
@@ -147,9 +134,7 @@ fn final_scene(scene: &mut Scene) {
     )));
 
     let mut boxes_2 = HittableList::default();
-    let white = Arc::new(Material::Lambertian {
-        albedo: Arc::new(SolidColor::new(Color::new(0.73, 0.73, 0.73))),
-    });
+    let white = Arc::new(Lambertian::from_color(Color::new(0.73, 0.73, 0.73)));
     let ns = 1000;
     for _ in 0..ns {
         boxes_2.add(Arc::new(Sphere::new(
@@ -173,28 +158,22 @@ fn cornell_smoke(scene: &mut Scene) {
     scene.set_image_width(600);
     scene.set_aspect_ratio(1.0);
     scene.samples_per_pixel = 50;
-    scene.background_color = Color::new(0.0, 0.0, 0.0);
+    scene.background = Arc::new(SolidBackground::new(Color::new(0.0, 0.0, 0.0)));
 
-    scene.camera.aperture = 0.0;
-    scene.camera.vfov = 40.0;
-    scene.camera.look_from = Point3::new(278.0, 278.0, -800.0);
-    scene.camera.look_at = Point3::new(278.0, 278.0, 0.0);
+    let mut camera = ThinLensCamera::default();
+    camera.aperture = 0.0;
+    camera.vfov = 40.0;
+    camera.look_from = Point3::new(278.0, 278.0, -800.0);
+    camera.look_at = Point3::new(278.0, 278.0, 0.0);
+    scene.camera = Box::new(camera);
 
     let world = &mut scene.world;
 
     // Materials
-    let red = Arc::new(Material::Lambertian {
-        albedo: Arc::new(SolidColor::new(Color::new(0.65, 0.05, 0.05))),
-    });
-    let white = Arc::new(Material::Lambertian {
-        albedo: Arc::new(SolidColor::new(Color::new(0.73, 0.73, 0.73))),
-    });
-    let green = Arc::new(Material::Lambertian {
-        albedo: Arc::new(SolidColor::new(Color::new(0.12, 0.45, 0.15))),
-    });
-    let light = Arc::new(Material::DiffuseLight {
-        emit: Arc::new(SolidColor::new(Color::new(7.0, 7.0, 7.0))),
-    });
+    let red = Arc::new(Lambertian::from_color(Color::new(0.65, 0.05, 0.05)));
+    let white = Arc::new(Lambertian::from_color(Color::new(0.73, 0.73, 0.73)));
+    let green = Arc::new(Lambertian::from_color(Color::new(0.12, 0.45, 0.15)));
+    let light = Arc::new(DiffuseLight::from_color(Color::new(7.0, 7.0, 7.0)));
 
     // Quads
     world.add(Arc::new(Quad::new(
@@ -269,30 +248,24 @@ fn cornell_box(scene: &mut Scene) {
     scene.set_aspect_ratio(1.0);
     scene.samples_per_pixel = 64;
     scene.max_depth = 50;
-    scene.background_color = Color::new(0.0, 0.0, 0.0);
+    scene.background = Arc::new(SolidBackground::new(Color::new(0.0, 0.0, 0.0)));
 
-    scene.camera.aperture = 0.0;
-    scene.camera.vfov = 40.0;
-    scene.camera.look_from = Point3::new(278.0, 278.0, -800.0);
-    scene.camera.look_at = Point3::new(278.0, 278.0, 0.0);
-    scene.camera.vup = DVec3::new(0.0, 1.0, 0.0);
-    scene.camera.focus_dist = 10.0;
+    let mut camera = ThinLensCamera::default();
+    camera.aperture = 0.0;
+    camera.vfov = 40.0;
+    camera.look_from = Point3::new(278.0, 278.0, -800.0);
+    camera.look_at = Point3::new(278.0, 278.0, 0.0);
+    camera.vup = DVec3::new(0.0, 1.0, 0.0);
+    camera.focus_dist = 10.0;
+    scene.camera = Box::new(camera);
 
     let world = &mut scene.world;
 
     // Materials
-    let red = Arc::new(Material::Lambertian {
-        albedo: Arc::new(SolidColor::new(Color::new(0.65, 0.05, 0.05))),
-    });
-    let white = Arc::new(Material::Lambertian {
-        albedo: Arc::new(SolidColor::new(Color::new(0.73, 0.73, 0.73))),
-    });
-    let green = Arc::new(Material::Lambertian {
-        albedo: Arc::new(SolidColor::new(Color::new(0.12, 0.45, 0.15))),
-    });
-    let light = Arc::new(Material::DiffuseLight {
-        emit: Arc::new(SolidColor::new(Color::new(15.0, 15.0, 15.0))),
-    });
+    let red = Arc::new(Lambertian::from_color(Color::new(0.65, 0.05, 0.05)));
+    let white = Arc::new(Lambertian::from_color(Color::new(0.73, 0.73, 0.73)));
+    let green = Arc::new(Lambertian::from_color(Color::new(0.12, 0.45, 0.15)));
+    let light = Arc::new(DiffuseLight::from_color(Color::new(15.0, 15.0, 15.0)));
 
     // Quads
     world.add(Arc::new(Quad::new(
@@ -353,9 +326,7 @@ fn cornell_box(scene: &mut Scene) {
     world.add(Arc::new(Sphere::new(
         Point3::new(160.0, 100.0, 145.0),
         100.0,
-        Arc::new(Material::Dielectric {
-            index_of_refraction: 1.5,
-        }),
+        Arc::new(Dielectric::new(1.5)),
     )));
 }
 
@@ -363,19 +334,19 @@ fn simple_light(scene: &mut Scene) {
     scene.set_image_width(400);
     scene.set_aspect_ratio(16.0 / 9.0);
     scene.samples_per_pixel = 100;
-    scene.background_color = Color::new(0.0, 0.0, 0.0);
+    scene.background = Arc::new(SolidBackground::new(Color::new(0.0, 0.0, 0.0)));
 
-    scene.camera.aperture = 0.0;
-    scene.camera.vfov = 20.0;
-    scene.camera.look_from = Point3::new(26.0, 3.0, 6.0);
-    scene.camera.look_at = Point3::new(0.0, 2.0, 0.0);
+    let mut camera = ThinLensCamera::default();
+    camera.aperture = 0.0;
+    camera.vfov = 20.0;
+    camera.look_from = Point3::new(26.0, 3.0, 6.0);
+    camera.look_at = Point3::new(0.0, 2.0, 0.0);
+    scene.camera = Box::new(camera);
 
     let world = &mut scene.world;
 
     let perlin_texture = Arc::new(NoiseTexture::new(4.0));
-    let perlin_material = Arc::new(Material::Lambertian {
-        albedo: perlin_texture.clone(),
-    });
+    let perlin_material = Arc::new(Lambertian::new(perlin_texture.clone()));
     world.add(Arc::new(Sphere::new(
         Point3::new(0.0, -1000.0, 0.0),
         1000.0,
@@ -387,9 +358,7 @@ fn simple_light(scene: &mut Scene) {
         perlin_material.clone(),
     )));
 
-    let diffuse_light = Arc::new(Material::DiffuseLight {
-        emit: Arc::new(SolidColor::new(Color::new(4.0, 4.0, 4.0))),
-    });
+    let diffuse_light = Arc::new(DiffuseLight::from_color(Color::new(4.0, 4.0, 4.0)));
     world.add(Arc::new(Sphere::new(
         Point3::new(0.0, 7.0, 0.0),
         2.0,
@@ -408,29 +377,21 @@ fn quads(scene: &mut Scene) {
     scene.set_aspect_ratio(1.0);
     scene.samples_per_pixel = 100;
 
-    scene.camera.aperture = 0.0;
-    scene.camera.vfov = 80.0;
-    scene.camera.look_from = Point3::new(0.0, 0.0, 9.0);
-    scene.camera.look_at = Point3::new(0.0, 0.0, 0.0);
+    let mut camera = ThinLensCamera::default();
+    camera.aperture = 0.0;
+    camera.vfov = 80.0;
+    camera.look_from = Point3::new(0.0, 0.0, 9.0);
+    camera.look_at = Point3::new(0.0, 0.0, 0.0);
+    scene.camera = Box::new(camera);
 
     let world = &mut scene.world;
 
     // Materials
-    let left_red = Arc::new(Material::Lambertian {
-        albedo: Arc::new(SolidColor::new(Color::new(1.0, 0.2, 0.2))),
-    });
-    let back_green = Arc::new(Material::Lambertian {
-        albedo: Arc::new(SolidColor::new(Color::new(0.2, 1.0, 0.2))),
-    });
-    let right_blue = Arc::new(Material::Lambertian {
-        albedo: Arc::new(SolidColor::new(Color::new(0.2, 0.2, 1.0))),
-    });
-    let upper_orange = Arc::new(Material::Lambertian {
-        albedo: Arc::new(SolidColor::new(Color::new(1.0, 0.5, 0.0))),
-    });
-    let lower_teal = Arc::new(Material::Lambertian {
-        albedo: Arc::new(SolidColor::new(Color::new(0.2, 0.8, 0.8))),
-    });
+    let left_red = Arc::new(Lambertian::from_color(Color::new(1.0, 0.2, 0.2)));
+    let back_green = Arc::new(Lambertian::from_color(Color::new(0.2, 1.0, 0.2)));
+    let right_blue = Arc::new(Lambertian::from_color(Color::new(0.2, 0.2, 1.0)));
+    let upper_orange = Arc::new(Lambertian::from_color(Color::new(1.0, 0.5, 0.0)));
+    let lower_teal = Arc::new(Lambertian::from_color(Color::new(0.2, 0.8, 0.8)));
 
     // Quads
     world.add(Arc::new(Quad::new(
@@ -470,17 +431,17 @@ fn two_perlin_spheres(scene: &mut Scene) {
     scene.set_aspect_ratio(16.0 / 9.0);
     scene.samples_per_pixel = 300;
 
-    scene.camera.aperture = 0.0;
-    scene.camera.vfov = 20.0;
-    scene.camera.look_from = Point3::new(13.0, 2.0, 3.0);
-    scene.camera.look_at = Point3::new(0.0, 0.0, 0.0);
+    let mut camera = ThinLensCamera::default();
+    camera.aperture = 0.0;
+    camera.vfov = 20.0;
+    camera.look_from = Point3::new(13.0, 2.0, 3.0);
+    camera.look_at = Point3::new(0.0, 0.0, 0.0);
+    scene.camera = Box::new(camera);
 
     let world = &mut scene.world;
 
     let perlin_texture = Arc::new(NoiseTexture::new(4.0));
-    let perlin_material = Arc::new(Material::Lambertian {
-        albedo: perlin_texture.clone(),
-    });
+    let perlin_material = Arc::new(Lambertian::new(perlin_texture.clone()));
     world.add(Arc::new(Sphere::new(
         Point3::new(0.0, -1000.0, 0.0),
         1000.0,
@@ -498,10 +459,12 @@ fn earth(scene: &mut Scene) {
     scene.set_aspect_ratio(16.0 / 9.0);
     scene.samples_per_pixel = 300;
 
-    scene.camera.aperture = 0.0;
-    scene.camera.vfov = 20.0;
-    scene.camera.look_from = Point3::new(0.0, 0.0, 12.0);
-    scene.camera.look_at = Point3::new(0.0, 0.0, 0.0);
+    let mut camera = ThinLensCamera::default();
+    camera.aperture = 0.0;
+    camera.vfov = 20.0;
+    camera.look_from = Point3::new(0.0, 0.0, 12.0);
+    camera.look_at = Point3::new(0.0, 0.0, 0.0);
+    scene.camera = Box::new(camera);
 
     let world = &mut scene.world;
 
@@ -510,9 +473,7 @@ fn earth(scene: &mut Scene) {
             .join("assets/images/earthmap.jpg")
             .as_path(),
     ));
-    let earth_surface = Arc::new(Material::Lambertian {
-        albedo: earth_texture.clone(),
-    });
+    let earth_surface = Arc::new(Lambertian::new(earth_texture.clone()));
     let globe = Arc::new(Sphere::new(
         Point3::new(0.0, 0.0, 0.0),
         2.0,
@@ -526,10 +487,12 @@ fn two_spheres(scene: &mut Scene) {
     scene.set_aspect_ratio(16.0 / 9.0);
     scene.samples_per_pixel = 100;
 
-    scene.camera.aperture = 0.0;
-    scene.camera.vfov = 20.0;
-    scene.camera.look_from = Point3::new(13.0, 2.0, 3.0);
-    scene.camera.look_at = Point3::new(0.0, 0.0, 0.0);
+    let mut camera = ThinLensCamera::default();
+    camera.aperture = 0.0;
+    camera.vfov = 20.0;
+    camera.look_from = Point3::new(13.0, 2.0, 3.0);
+    camera.look_at = Point3::new(0.0, 0.0, 0.0);
+    scene.camera = Box::new(camera);
 
     let world = &mut scene.world;
 
@@ -538,9 +501,7 @@ fn two_spheres(scene: &mut Scene) {
         Color::new(0.2, 0.3, 0.1),
         Color::new(0.9, 0.9, 0.9),
     ));
-    let material_checker = Arc::new(Material::Lambertian {
-        albedo: checker.clone(),
-    });
+    let material_checker = Arc::new(Lambertian::new(checker.clone()));
     world.add(Arc::new(Sphere::new(
         Point3::new(0.0, -10.0, 0.0),
         10.0,
@@ -559,12 +520,14 @@ fn random_scene(scene: &mut Scene) {
     scene.set_image_width(300);
     scene.samples_per_pixel = 100;
 
-    scene.camera.look_from = Point3::new(13.0, 2.0, 3.0);
-    scene.camera.look_at = Point3::new(0.0, 0.0, 0.0);
-    scene.camera.vup = DVec3::new(0.0, 1.0, 0.0);
-    scene.camera.vfov = 20.0;
-    scene.camera.aperture = 0.1;
-    scene.camera.focus_dist = 10.0;
+    let mut camera = ThinLensCamera::default();
+    camera.look_from = Point3::new(13.0, 2.0, 3.0);
+    camera.look_at = Point3::new(0.0, 0.0, 0.0);
+    camera.vup = DVec3::new(0.0, 1.0, 0.0);
+    camera.vfov = 20.0;
+    camera.aperture = 0.1;
+    camera.focus_dist = 10.0;
+    scene.camera = Box::new(camera);
 
     // let world = &mut scene.world;
     let mut world = HittableList::default();
@@ -574,12 +537,8 @@ fn random_scene(scene: &mut Scene) {
         Color::new(0.2, 0.3, 0.1),
         Color::new(0.9, 0.9, 0.9),
     ));
-    let material_ground = Arc::new(Material::Lambertian {
-        albedo: checker.clone(),
-    });
-    // let material_ground = Arc::new(Material::Lambertian {
-    //     albedo: Color::new(0.5, 0.5, 0.5),
-    // });
+    let material_ground = Arc::new(Lambertian::new(checker.clone()));
+    // let material_ground = Arc::new(Lambertian::from_color(Color::new(0.5, 0.5, 0.5)));
     world.add(Arc::new(Sphere::new(
         Point3::new(0.0, -1000.0, 0.0),
         1000.0,
@@ -600,9 +559,7 @@ fn random_scene(scene: &mut Scene) {
                     // diffuse
                     let albedo = Color::new(random_f64(), random_f64(), random_f64())
                         * Color::new(random_f64(), random_f64(), random_f64());
-                    let sphere_material = Arc::new(Material::Lambertian {
-                        albedo: Arc::new(SolidColor::new(albedo)),
-                    });
+                    let sphere_material = Arc::new(Lambertian::from_color(albedo));
                     let center_2 = center + DVec3::new(0.0, random_f64_range(0.0, 0.5), 0.0);
                     // world.add(Arc::new(MovingSphere::new(
                     //     center,
@@ -619,39 +576,30 @@ fn random_scene(scene: &mut Scene) {
                         random_f64_range(0.5, 1.0),
                     );
                     let fuzz = random_f64_range(0.0, 0.5);
-                    let sphere_material = Arc::new(Material::Metal { albedo, fuzz });
+                    let sphere_material = Arc::new(Metal::new(albedo, fuzz));
                     world.add(Arc::new(Sphere::new(center, 0.2, sphere_material.clone())));
                 } else {
                     // glass
-                    let sphere_material = Arc::new(Material::Dielectric {
-                        index_of_refraction: 1.5,
-                    });
+                    let sphere_material = Arc::new(Dielectric::new(1.5));
                     world.add(Arc::new(Sphere::new(center, 0.2, sphere_material.clone())));
                 }
             }
         }
     }
 
-    let material_1 = Arc::new(Material::Dielectric {
-        index_of_refraction: 1.5,
-    });
+    let material_1 = Arc::new(Dielectric::new(1.5));
     world.add(Arc::new(Sphere::new(
         Point3::new(0.0, 1.0, 0.0),
         1.0,
         material_1.clone(),
     )));
-    let material_2 = Arc::new(Material::Lambertian {
-        albedo: Arc::new(SolidColor::new(Color::new(0.4, 0.2, 0.1))),
-    });
+    let material_2 = Arc::new(Lambertian::from_color(Color::new(0.4, 0.2, 0.1)));
     world.add(Arc::new(Sphere::new(
         Point3::new(-4.0, 1.0, 0.0),
         1.0,
         material_2.clone(),
     )));
-    let material_3 = Arc::new(Material::Metal {
-        albedo: Color::new(0.7, 0.6, 0.5),
-        fuzz: 0.0,
-    });
+    let material_3 = Arc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
     world.add(Arc::new(Sphere::new(
         Point3::new(4.0, 1.0, 0.0),
         1.0,
@@ -664,9 +612,7 @@ fn random_scene(scene: &mut Scene) {
 fn main() {
     let mut scene = Scene::new(ASPECT_RATIO, WIDTH, SAMPLES_PER_PIXEL, MAX_DEPTH);
 
-    scene.background_color = Color::new(0.7, 0.8, 1.0);
-    scene.camera.vup = DVec3::new(0.0, 1.0, 0.0);
-    scene.camera.focus_dist = 10.0;
+    scene.background = Arc::new(SolidBackground::new(Color::new(0.7, 0.8, 1.0)));
 
     // random_scene(&mut scene);
     // two_spheres(&mut scene);
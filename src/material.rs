@@ -1,92 +1,1706 @@
 use crate::{
-    color::Color, hittable::HitRecord, random_f64, random_in_unit_sphere, random_unit_vertor,
-    ray::Ray, texture::Texture, Point3,
+    clamp,
+    color::{blackbody, luminance, Color},
+    hittable::HitRecord,
+    ies::IesProfile,
+    microfacet::MicrofacetDistribution,
+    onb::Onb,
+    random_cosine_direction, random_f64, random_in_unit_sphere, random_unit_vertor,
+    ray::{Ray, RayKind},
+    texture::{ImageTexture, SolidColor, Texture},
+    thin_film::ThinFilm,
+    Point3,
 };
 use glam::DVec3;
-use std::sync::Arc;
+use std::{f64::consts::PI, path::Path, sync::Arc};
 
-pub enum Material {
-    Lambertian { albedo: Arc<dyn Texture> },
-    Metal { albedo: Color, fuzz: f64 },
-    Dielectric { index_of_refraction: f64 },
-    DiffuseLight { emit: Arc<dyn Texture> },
-    Isotropic { albedo: Arc<dyn Texture> },
+pub trait Material: Sync + Send {
+    fn emitted(&self, _in_ray: &Ray, _hit_record: &HitRecord) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    /// Returns the attenuation and the scattered ray, together with the lobe the ray was
+    /// sampled from.
+    fn scatter(&self, _in_ray: &Ray, _hit_record: &HitRecord) -> Option<(Color, Ray, Lobe)> {
+        None
+    }
+
+    /// Returns the BSDF times the cosine of the angle between the normal and the unit `direction`
+    /// towards the light, used for next-event estimation. Perfectly specular materials, such as
+    /// mirrors and smooth glass, return black.
+    fn eval(&self, in_ray: &Ray, hit_record: &HitRecord, direction: DVec3) -> Color;
+
+    /// Returns the solid angle density with which `scatter` generates the unit `direction`, used
+    /// for multiple importance sampling. Perfectly specular materials return zero.
+    fn pdf(&self, in_ray: &Ray, hit_record: &HitRecord, direction: DVec3) -> f64;
+
+    /// Returns false if `eval` only approximates the scattering, e.g. for random walks that
+    /// leave the surface somewhere else. The renderer then only uses it for lights that rays
+    /// cannot hit and relies on the scattered rays to find all other light.
+    fn is_evaluable(&self) -> bool {
+        true
+    }
+}
+
+/// The kind of scattering a material sampled a ray from.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Lobe {
+    Diffuse,
+    Glossy,
+    SpecularReflection,
+    Transmission { specular: bool },
 }
 
-impl Material {
-    pub fn emitted(&self, u: f64, v: f64, point: &Point3) -> Color {
+impl Lobe {
+    /// Returns true if the lobe scatters into a single direction, which no other sampling
+    /// strategy can find and which `Material::pdf` does not account for.
+    pub fn is_delta(&self) -> bool {
+        matches!(
+            self,
+            Self::SpecularReflection | Self::Transmission { specular: true }
+        )
+    }
+
+    /// Returns the kind of the rays scattered from the lobe.
+    pub fn ray_kind(&self) -> RayKind {
         match self {
-            Material::DiffuseLight { emit } => emit.value(u, v, point),
-            _ => Color::new(0.0, 0.0, 0.0),
+            Self::Diffuse => RayKind::Diffuse,
+            Self::Glossy => RayKind::Glossy,
+            Self::SpecularReflection => RayKind::Reflection,
+            Self::Transmission { .. } => RayKind::Transmission,
         }
     }
+}
 
-    pub fn scatter(&self, in_ray: &Ray, hit_record: &HitRecord) -> Option<(Color, Ray)> {
-        match self {
-            Material::Lambertian { albedo } => {
-                let mut scatter_direction = hit_record.normal + random_unit_vertor();
+pub struct Lambertian {
+    albedo: Arc<dyn Texture>,
+}
 
-                // Catch degenerate scatter direction
-                if near_zero(&scatter_direction) {
-                    scatter_direction = hit_record.normal;
-                }
+impl Lambertian {
+    pub fn new(albedo: Arc<dyn Texture>) -> Self {
+        Self { albedo }
+    }
+
+    pub fn from_color(color: Color) -> Self {
+        Self {
+            albedo: Arc::new(SolidColor::new(color)),
+        }
+    }
+}
+
+impl Material for Lambertian {
+    fn scatter(&self, in_ray: &Ray, hit_record: &HitRecord) -> Option<(Color, Ray, Lobe)> {
+        let mut scatter_direction = hit_record.normal + random_unit_vertor();
+
+        // Catch degenerate scatter direction
+        if near_zero(&scatter_direction) {
+            scatter_direction = hit_record.normal;
+        }
+
+        let scattered_ray = Ray::new(hit_record.point, scatter_direction, in_ray.time);
+        let attenuation = self
+            .albedo
+            .value(hit_record.u, hit_record.v, &hit_record.point);
+
+        Some((attenuation, scattered_ray, Lobe::Diffuse))
+    }
+
+    fn eval(&self, _in_ray: &Ray, hit_record: &HitRecord, direction: DVec3) -> Color {
+        let cos_theta = direction.dot(hit_record.normal);
+        if cos_theta <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+
+        self.albedo
+            .value(hit_record.u, hit_record.v, &hit_record.point)
+            * cos_theta
+            / PI
+    }
+
+    fn pdf(&self, _in_ray: &Ray, hit_record: &HitRecord, direction: DVec3) -> f64 {
+        direction.dot(hit_record.normal).max(0.0) / PI
+    }
+}
+
+/// Oren-Nayar rough diffuse reflection for clay, concrete, fabric and similar surfaces, where
+/// `sigma` is the standard deviation of the microfacet slope angle in degrees.
+pub struct OrenNayar {
+    albedo: Arc<dyn Texture>,
+    a: f64,
+    b: f64,
+}
+
+impl OrenNayar {
+    pub fn new(albedo: Arc<dyn Texture>, sigma: f64) -> Self {
+        let sigma = sigma.to_radians();
+        let sigma2 = sigma * sigma;
+
+        Self {
+            albedo,
+            a: 1.0 - sigma2 / (2.0 * (sigma2 + 0.33)),
+            b: 0.45 * sigma2 / (sigma2 + 0.09),
+        }
+    }
+
+    pub fn from_color(color: Color, sigma: f64) -> Self {
+        Self::new(Arc::new(SolidColor::new(color)), sigma)
+    }
+}
+
+impl OrenNayar {
+    /// Returns the Oren-Nayar factor that scales the Lambertian reflectance for the given local
+    /// directions.
+    fn roughness_factor(&self, wo: DVec3, wi: DVec3) -> f64 {
+        let sin_theta_i = f64::max(0.0, 1.0 - wi.z * wi.z).sqrt();
+        let sin_theta_o = f64::max(0.0, 1.0 - wo.z * wo.z).sqrt();
+
+        // Cosine of the azimuthal difference between the two directions
+        let max_cos = if sin_theta_i > 1e-4 && sin_theta_o > 1e-4 {
+            f64::max(
+                0.0,
+                (wi.x * wo.x + wi.y * wo.y) / (sin_theta_i * sin_theta_o),
+            )
+        } else {
+            0.0
+        };
+
+        let (sin_alpha, tan_beta) = if wi.z.abs() > wo.z.abs() {
+            (sin_theta_o, sin_theta_i / wi.z.abs())
+        } else {
+            (sin_theta_i, sin_theta_o / wo.z.abs())
+        };
+
+        self.a + self.b * max_cos * sin_alpha * tan_beta
+    }
+}
+
+impl Material for OrenNayar {
+    fn scatter(&self, in_ray: &Ray, hit_record: &HitRecord) -> Option<(Color, Ray, Lobe)> {
+        let onb = Onb::from_w(hit_record.normal);
+        let wo = onb.to_local(-in_ray.direction.normalize());
+        let wi = random_cosine_direction();
+
+        // The cosine-weighted sampling density cancels the cosine term and the 1/pi factor
+        let albedo = self
+            .albedo
+            .value(hit_record.u, hit_record.v, &hit_record.point);
+        let attenuation = albedo * self.roughness_factor(wo, wi);
+
+        Some((
+            attenuation,
+            Ray::new(hit_record.point, onb.local(wi), in_ray.time),
+            Lobe::Diffuse,
+        ))
+    }
+
+    fn eval(&self, in_ray: &Ray, hit_record: &HitRecord, direction: DVec3) -> Color {
+        let onb = Onb::from_w(hit_record.normal);
+        let wo = onb.to_local(-in_ray.direction.normalize());
+        let wi = onb.to_local(direction);
+        if wi.z <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+
+        let albedo = self
+            .albedo
+            .value(hit_record.u, hit_record.v, &hit_record.point);
+        albedo * self.roughness_factor(wo, wi) * wi.z / PI
+    }
+
+    fn pdf(&self, _in_ray: &Ray, hit_record: &HitRecord, direction: DVec3) -> f64 {
+        direction.dot(hit_record.normal).max(0.0) / PI
+    }
+}
+
+/// A retro-reflective surface, such as road signs or safety vests, that sends a
+/// `retroreflectance` fraction of the light back towards where it came from in a Phong-shaped
+/// lobe of the given `exponent`, and diffusely reflects the rest.
+pub struct Retroreflective {
+    albedo: Arc<dyn Texture>,
+    retroreflectance: f64,
+    exponent: f64,
+}
 
-                let scattered_ray = Ray::new(hit_record.point, scatter_direction, in_ray.time);
-                let attenuation = albedo.value(hit_record.u, hit_record.v, &hit_record.point);
+impl Retroreflective {
+    pub fn new(albedo: Arc<dyn Texture>, retroreflectance: f64, exponent: f64) -> Self {
+        Self {
+            albedo,
+            retroreflectance,
+            exponent,
+        }
+    }
+
+    pub fn from_color(color: Color, retroreflectance: f64, exponent: f64) -> Self {
+        Self::new(Arc::new(SolidColor::new(color)), retroreflectance, exponent)
+    }
+}
+
+impl Material for Retroreflective {
+    fn scatter(&self, in_ray: &Ray, hit_record: &HitRecord) -> Option<(Color, Ray, Lobe)> {
+        let attenuation = self
+            .albedo
+            .value(hit_record.u, hit_record.v, &hit_record.point);
 
-                Some((attenuation, scattered_ray))
+        let (direction, lobe) = if random_f64() < self.retroreflectance {
+            // Sample the Phong lobe around the direction back towards the incoming ray
+            let onb = Onb::from_w(-in_ray.direction);
+            let cos_psi = random_f64().powf(1.0 / (self.exponent + 1.0));
+            let sin_psi = f64::max(0.0, 1.0 - cos_psi * cos_psi).sqrt();
+            let phi = 2.0 * PI * random_f64();
+            let direction = onb.local(DVec3::new(
+                sin_psi * phi.cos(),
+                sin_psi * phi.sin(),
+                cos_psi,
+            ));
+
+            // Light retro-reflected below the horizon is absorbed
+            if direction.dot(hit_record.normal) <= 0.0 {
+                return None;
             }
-            Material::Metal { albedo, fuzz } => {
-                let reflected = reflect(in_ray.direction.normalize(), hit_record.normal);
-                let scattered_ray = Ray::new(
-                    hit_record.point,
-                    reflected + *fuzz * random_in_unit_sphere(),
-                    in_ray.time,
-                );
-                let attenuation = *albedo;
-
-                if scattered_ray.direction.dot(hit_record.normal) > 0.0 {
-                    Some((attenuation, scattered_ray))
+            (direction, Lobe::Glossy)
+        } else {
+            (
+                Onb::from_w(hit_record.normal).local(random_cosine_direction()),
+                Lobe::Diffuse,
+            )
+        };
+
+        Some((
+            attenuation,
+            Ray::new(hit_record.point, direction, in_ray.time),
+            lobe,
+        ))
+    }
+
+    fn eval(&self, in_ray: &Ray, hit_record: &HitRecord, direction: DVec3) -> Color {
+        // Both lobes are sampled exactly, so the weight of a scattered ray is the albedo
+        self.albedo
+            .value(hit_record.u, hit_record.v, &hit_record.point)
+            * self.pdf(in_ray, hit_record, direction)
+    }
+
+    fn pdf(&self, in_ray: &Ray, hit_record: &HitRecord, direction: DVec3) -> f64 {
+        let cos_theta = direction.dot(hit_record.normal);
+        if cos_theta <= 0.0 {
+            return 0.0;
+        }
+
+        let cos_psi = direction.dot(-in_ray.direction.normalize()).max(0.0);
+        let retroreflection_pdf = (self.exponent + 1.0) / (2.0 * PI) * cos_psi.powf(self.exponent);
+
+        self.retroreflectance * retroreflection_pdf + (1.0 - self.retroreflectance) * cos_theta / PI
+    }
+}
+
+pub struct Metal {
+    albedo: Color,
+    fuzz: f64,
+}
+
+impl Metal {
+    pub fn new(albedo: Color, fuzz: f64) -> Self {
+        Self { albedo, fuzz }
+    }
+
+    /// Returns the solid angle density of the mirror direction offset by a point uniformly
+    /// distributed in a ball of radius `fuzz`, along the unit `direction`.
+    fn fuzz_pdf(&self, reflected: DVec3, direction: DVec3) -> f64 {
+        // Integrate r^2 dr along the part of the ray inside the ball over the volume of the ball
+        let b = direction.dot(reflected);
+        let discriminant = b * b - reflected.length_squared() + self.fuzz * self.fuzz;
+        if discriminant <= 0.0 {
+            return 0.0;
+        }
+
+        let t_far = (b + discriminant.sqrt()).max(0.0);
+        let t_near = (b - discriminant.sqrt()).max(0.0);
+        (t_far.powi(3) - t_near.powi(3)) / (4.0 * PI * self.fuzz.powi(3))
+    }
+}
+
+impl Material for Metal {
+    fn scatter(&self, in_ray: &Ray, hit_record: &HitRecord) -> Option<(Color, Ray, Lobe)> {
+        let reflected = reflect(in_ray.direction.normalize(), hit_record.normal);
+        let scattered_ray = Ray::new(
+            hit_record.point,
+            reflected + self.fuzz * random_in_unit_sphere(),
+            in_ray.time,
+        );
+        let attenuation = self.albedo;
+
+        let lobe = if self.fuzz > 0.0 {
+            Lobe::Glossy
+        } else {
+            Lobe::SpecularReflection
+        };
+
+        if scattered_ray.direction.dot(hit_record.normal) > 0.0 {
+            Some((attenuation, scattered_ray, lobe))
+        } else {
+            None
+        }
+    }
+
+    fn eval(&self, in_ray: &Ray, hit_record: &HitRecord, direction: DVec3) -> Color {
+        // The weight of a scattered ray is the albedo, as the fuzzed direction is sampled exactly
+        self.albedo * self.pdf(in_ray, hit_record, direction)
+    }
+
+    fn pdf(&self, in_ray: &Ray, hit_record: &HitRecord, direction: DVec3) -> f64 {
+        // Without fuzz the metal is a perfect mirror
+        if self.fuzz <= 0.0 || direction.dot(hit_record.normal) <= 0.0 {
+            return 0.0;
+        }
+
+        let reflected = reflect(in_ray.direction.normalize(), hit_record.normal);
+        self.fuzz_pdf(reflected, direction)
+    }
+}
+
+/// A microfacet conductor whose Fresnel reflectance follows Schlick's approximation.
+pub struct RoughConductor {
+    albedo: Color,
+    distribution: MicrofacetDistribution,
+}
+
+impl RoughConductor {
+    pub fn new(albedo: Color, distribution: MicrofacetDistribution) -> Self {
+        Self {
+            albedo,
+            distribution,
+        }
+    }
+}
+
+impl Material for RoughConductor {
+    fn scatter(&self, in_ray: &Ray, hit_record: &HitRecord) -> Option<(Color, Ray, Lobe)> {
+        scatter_microfacet_reflection(in_ray, hit_record, &self.distribution, |cos_theta| {
+            schlick_fresnel(self.albedo, cos_theta)
+        })
+    }
+
+    fn eval(&self, in_ray: &Ray, hit_record: &HitRecord, direction: DVec3) -> Color {
+        eval_microfacet_reflection(
+            in_ray,
+            hit_record,
+            direction,
+            &self.distribution,
+            |cos_theta| schlick_fresnel(self.albedo, cos_theta),
+        )
+    }
+
+    fn pdf(&self, in_ray: &Ray, hit_record: &HitRecord, direction: DVec3) -> f64 {
+        pdf_microfacet_reflection(in_ray, hit_record, direction, &self.distribution)
+    }
+}
+
+/// A microfacet conductor described by its complex index of refraction `eta + i k`.
+pub struct Conductor {
+    eta: Color,
+    k: Color,
+    distribution: MicrofacetDistribution,
+    thin_film: Option<ThinFilm>,
+}
+
+impl Conductor {
+    pub fn new(eta: Color, k: Color, distribution: MicrofacetDistribution) -> Self {
+        Self {
+            eta,
+            k,
+            distribution,
+            thin_film: None,
+        }
+    }
+
+    pub fn with_thin_film(mut self, thin_film: ThinFilm) -> Self {
+        self.thin_film = Some(thin_film);
+        self
+    }
+
+    pub fn from_preset(preset: MetalPreset, distribution: MicrofacetDistribution) -> Self {
+        let (eta, k) = preset.eta_k();
+        Self::new(eta, k, distribution)
+    }
+}
+
+impl Conductor {
+    fn fresnel(&self, hit_record: &HitRecord, cos_theta: f64) -> Color {
+        match &self.thin_film {
+            Some(thin_film) => thin_film.reflectance(hit_record, cos_theta, 1.0, self.eta, self.k),
+            None => Color::new(
+                fresnel_conductor(cos_theta, self.eta.x, self.k.x),
+                fresnel_conductor(cos_theta, self.eta.y, self.k.y),
+                fresnel_conductor(cos_theta, self.eta.z, self.k.z),
+            ),
+        }
+    }
+}
+
+impl Material for Conductor {
+    fn scatter(&self, in_ray: &Ray, hit_record: &HitRecord) -> Option<(Color, Ray, Lobe)> {
+        scatter_microfacet_reflection(in_ray, hit_record, &self.distribution, |cos_theta| {
+            self.fresnel(hit_record, cos_theta)
+        })
+    }
+
+    fn eval(&self, in_ray: &Ray, hit_record: &HitRecord, direction: DVec3) -> Color {
+        eval_microfacet_reflection(
+            in_ray,
+            hit_record,
+            direction,
+            &self.distribution,
+            |cos_theta| self.fresnel(hit_record, cos_theta),
+        )
+    }
+
+    fn pdf(&self, in_ray: &Ray, hit_record: &HitRecord, direction: DVec3) -> f64 {
+        pdf_microfacet_reflection(in_ray, hit_record, direction, &self.distribution)
+    }
+}
+
+/// Measured complex indices of refraction for common metals, reduced to RGB.
+#[derive(Clone, Copy)]
+pub enum MetalPreset {
+    Aluminium,
+    Chromium,
+    Copper,
+    Gold,
+    Iron,
+    Platinum,
+    Silver,
+    Titanium,
+    Tungsten,
+}
+
+impl MetalPreset {
+    /// Returns the real (`eta`) and imaginary (`k`) parts of the index of refraction.
+    pub fn eta_k(&self) -> (Color, Color) {
+        match self {
+            MetalPreset::Aluminium => (
+                Color::new(1.657460, 0.880369, 0.521229),
+                Color::new(9.223869, 6.269523, 4.837001),
+            ),
+            MetalPreset::Chromium => (
+                Color::new(4.368030, 2.916700, 1.654700),
+                Color::new(5.206400, 4.231300, 3.754500),
+            ),
+            MetalPreset::Copper => (
+                Color::new(0.200438, 0.924033, 1.102212),
+                Color::new(3.912949, 2.452848, 2.142188),
+            ),
+            MetalPreset::Gold => (
+                Color::new(0.143119, 0.374957, 1.442479),
+                Color::new(3.983160, 2.385721, 1.603215),
+            ),
+            MetalPreset::Iron => (
+                Color::new(2.911400, 2.949700, 2.584500),
+                Color::new(3.089300, 2.931800, 2.767000),
+            ),
+            MetalPreset::Platinum => (
+                Color::new(2.375700, 2.084700, 1.845300),
+                Color::new(4.265500, 3.715300, 3.136500),
+            ),
+            MetalPreset::Silver => (
+                Color::new(0.155265, 0.116723, 0.138342),
+                Color::new(4.828181, 3.122249, 2.146961),
+            ),
+            MetalPreset::Titanium => (
+                Color::new(2.740700, 2.541800, 2.267000),
+                Color::new(3.814300, 3.434500, 3.038500),
+            ),
+            MetalPreset::Tungsten => (
+                Color::new(4.370700, 3.300200, 2.998200),
+                Color::new(3.500600, 2.600300, 2.224800),
+            ),
+        }
+    }
+}
+
+pub struct Dielectric {
+    index_of_refraction: f64,
+    thin_film: Option<ThinFilm>,
+}
+
+impl Dielectric {
+    pub fn new(index_of_refraction: f64) -> Self {
+        Self {
+            index_of_refraction,
+            thin_film: None,
+        }
+    }
+
+    pub fn with_thin_film(mut self, thin_film: ThinFilm) -> Self {
+        self.thin_film = Some(thin_film);
+        self
+    }
+}
+
+impl Material for Dielectric {
+    fn scatter(&self, in_ray: &Ray, hit_record: &HitRecord) -> Option<(Color, Ray, Lobe)> {
+        let refraction_ratio = if hit_record.front_face {
+            1.0 / self.index_of_refraction
+        } else {
+            self.index_of_refraction
+        };
+        let unit_direction = in_ray.direction.normalize();
+
+        let cos_theta = f64::min(-unit_direction.dot(hit_record.normal), 1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+
+        let cant_refract = refraction_ratio * sin_theta > 1.0;
+
+        // A thin film makes the reflectance depend on the color channel
+        let reflectance = match &self.thin_film {
+            Some(thin_film) => {
+                let (eta_incident, eta_transmitted) = if hit_record.front_face {
+                    (1.0, self.index_of_refraction)
                 } else {
-                    None
+                    (self.index_of_refraction, 1.0)
+                };
+                thin_film.reflectance(
+                    hit_record,
+                    cos_theta,
+                    eta_incident,
+                    Color::splat(eta_transmitted),
+                    Color::ZERO,
+                )
+            }
+            None => Color::splat(schlick_reflectance(cos_theta, refraction_ratio)),
+        };
+        let reflect_probability = (reflectance.x + reflectance.y + reflectance.z) / 3.0;
+
+        let (direction, attenuation, lobe) = if cant_refract {
+            (
+                reflect(unit_direction, hit_record.normal),
+                Color::new(1.0, 1.0, 1.0),
+                Lobe::SpecularReflection,
+            )
+        } else if reflect_probability > random_f64() {
+            (
+                reflect(unit_direction, hit_record.normal),
+                reflectance / reflect_probability,
+                Lobe::SpecularReflection,
+            )
+        } else {
+            (
+                refract(unit_direction, hit_record.normal, refraction_ratio),
+                (Color::ONE - reflectance) / (1.0 - reflect_probability),
+                Lobe::Transmission { specular: true },
+            )
+        };
+
+        let scattered_ray = Ray::new(hit_record.point, direction, in_ray.time);
+        Some((attenuation, scattered_ray, lobe))
+    }
+
+    fn eval(&self, _in_ray: &Ray, _hit_record: &HitRecord, _direction: DVec3) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    fn pdf(&self, _in_ray: &Ray, _hit_record: &HitRecord, _direction: DVec3) -> f64 {
+        0.0
+    }
+}
+
+/// A dielectric with a GGX microfacet interface, e.g. frosted glass.
+pub struct RoughDielectric {
+    index_of_refraction: f64,
+    roughness: Arc<dyn Texture>,
+}
+
+impl RoughDielectric {
+    pub fn new(index_of_refraction: f64, roughness: Arc<dyn Texture>) -> Self {
+        Self {
+            index_of_refraction,
+            roughness,
+        }
+    }
+}
+
+impl RoughDielectric {
+    /// Returns the microfacet distribution at the hit point and the relative index of refraction
+    /// of the side the ray is heading into.
+    fn interface(&self, hit_record: &HitRecord) -> (MicrofacetDistribution, f64) {
+        let roughness = self
+            .roughness
+            .scalar_value(hit_record.u, hit_record.v, &hit_record.point);
+        let distribution = MicrofacetDistribution::trowbridge_reitz(
+            MicrofacetDistribution::roughness_to_alpha(roughness),
+        );
+        let eta = if hit_record.front_face {
+            self.index_of_refraction
+        } else {
+            1.0 / self.index_of_refraction
+        };
+
+        (distribution, eta)
+    }
+}
+
+impl Material for RoughDielectric {
+    fn scatter(&self, in_ray: &Ray, hit_record: &HitRecord) -> Option<(Color, Ray, Lobe)> {
+        let (distribution, eta) = self.interface(hit_record);
+
+        scatter_microfacet_dielectric(in_ray, hit_record, &distribution, eta)
+    }
+
+    fn eval(&self, in_ray: &Ray, hit_record: &HitRecord, direction: DVec3) -> Color {
+        let (distribution, eta) = self.interface(hit_record);
+        let onb = Onb::from_w(hit_record.normal);
+        let wo = onb.to_local(-in_ray.direction.normalize());
+
+        Color::ONE * eval_microfacet_dielectric(wo, onb.to_local(direction), &distribution, eta)
+    }
+
+    fn pdf(&self, in_ray: &Ray, hit_record: &HitRecord, direction: DVec3) -> f64 {
+        let (distribution, eta) = self.interface(hit_record);
+        let onb = Onb::from_w(hit_record.normal);
+        let wo = onb.to_local(-in_ray.direction.normalize());
+
+        pdf_microfacet_dielectric(wo, onb.to_local(direction), &distribution, eta)
+    }
+}
+
+/// An infinitesimally thin dielectric slab, such as a window pane or a soap bubble, meant for
+/// single-sided geometry. Light passes straight through without refraction and the internal
+/// reflections between the two interfaces are summed analytically.
+pub struct ThinDielectric {
+    index_of_refraction: f64,
+}
+
+impl ThinDielectric {
+    pub fn new(index_of_refraction: f64) -> Self {
+        Self {
+            index_of_refraction,
+        }
+    }
+}
+
+impl Material for ThinDielectric {
+    fn scatter(&self, in_ray: &Ray, hit_record: &HitRecord) -> Option<(Color, Ray, Lobe)> {
+        let unit_direction = in_ray.direction.normalize();
+        let cos_theta = -unit_direction.dot(hit_record.normal);
+
+        // Geometric series of the light bouncing back and forth inside the slab
+        let mut reflectance = fresnel_dielectric(cos_theta, self.index_of_refraction);
+        if reflectance < 1.0 {
+            reflectance +=
+                (1.0 - reflectance).powi(2) * reflectance / (1.0 - reflectance * reflectance);
+        }
+
+        let (direction, lobe) = if random_f64() < reflectance {
+            (
+                reflect(unit_direction, hit_record.normal),
+                Lobe::SpecularReflection,
+            )
+        } else {
+            (unit_direction, Lobe::Transmission { specular: true })
+        };
+
+        Some((
+            Color::new(1.0, 1.0, 1.0),
+            Ray::new(hit_record.point, direction, in_ray.time),
+            lobe,
+        ))
+    }
+
+    fn eval(&self, _in_ray: &Ray, _hit_record: &HitRecord, _direction: DVec3) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    fn pdf(&self, _in_ray: &Ray, _hit_record: &HitRecord, _direction: DVec3) -> f64 {
+        0.0
+    }
+}
+
+/// Diffuse reflection and diffuse transmission for thin translucent surfaces such as paper,
+/// leaves and lampshades. Light passing through leaves the back side with a cosine distribution.
+pub struct DiffuseTransmission {
+    reflectance: Arc<dyn Texture>,
+    transmittance: Arc<dyn Texture>,
+}
+
+impl DiffuseTransmission {
+    pub fn new(reflectance: Arc<dyn Texture>, transmittance: Arc<dyn Texture>) -> Self {
+        Self {
+            reflectance,
+            transmittance,
+        }
+    }
+
+    pub fn from_colors(reflectance: Color, transmittance: Color) -> Self {
+        Self::new(
+            Arc::new(SolidColor::new(reflectance)),
+            Arc::new(SolidColor::new(transmittance)),
+        )
+    }
+}
+
+impl DiffuseTransmission {
+    /// Returns the probability of sampling the reflected side, chosen in proportion to how much
+    /// light each side receives, or `None` if the surface absorbs everything.
+    fn reflect_probability(reflectance: Color, transmittance: Color) -> Option<f64> {
+        let reflect_weight = reflectance.x + reflectance.y + reflectance.z;
+        let transmit_weight = transmittance.x + transmittance.y + transmittance.z;
+        if reflect_weight + transmit_weight <= 0.0 {
+            return None;
+        }
+
+        Some(reflect_weight / (reflect_weight + transmit_weight))
+    }
+}
+
+impl Material for DiffuseTransmission {
+    fn scatter(&self, in_ray: &Ray, hit_record: &HitRecord) -> Option<(Color, Ray, Lobe)> {
+        let (u, v, point) = (hit_record.u, hit_record.v, &hit_record.point);
+        let reflectance = self.reflectance.value(u, v, point);
+        let transmittance = self.transmittance.value(u, v, point);
+        let reflect_probability = Self::reflect_probability(reflectance, transmittance)?;
+
+        let (normal, attenuation, lobe) = if random_f64() < reflect_probability {
+            (
+                hit_record.normal,
+                reflectance / reflect_probability,
+                Lobe::Diffuse,
+            )
+        } else {
+            (
+                -hit_record.normal,
+                transmittance / (1.0 - reflect_probability),
+                Lobe::Transmission { specular: false },
+            )
+        };
+        let direction = Onb::from_w(normal).local(random_cosine_direction());
+
+        Some((
+            attenuation,
+            Ray::new(hit_record.point, direction, in_ray.time),
+            lobe,
+        ))
+    }
+
+    fn eval(&self, _in_ray: &Ray, hit_record: &HitRecord, direction: DVec3) -> Color {
+        let (u, v, point) = (hit_record.u, hit_record.v, &hit_record.point);
+        let cos_theta = direction.dot(hit_record.normal);
+
+        if cos_theta > 0.0 {
+            self.reflectance.value(u, v, point) * cos_theta / PI
+        } else {
+            self.transmittance.value(u, v, point) * -cos_theta / PI
+        }
+    }
+
+    fn pdf(&self, _in_ray: &Ray, hit_record: &HitRecord, direction: DVec3) -> f64 {
+        let (u, v, point) = (hit_record.u, hit_record.v, &hit_record.point);
+        let Some(reflect_probability) = Self::reflect_probability(
+            self.reflectance.value(u, v, point),
+            self.transmittance.value(u, v, point),
+        ) else {
+            return 0.0;
+        };
+        let cos_theta = direction.dot(hit_record.normal);
+
+        if cos_theta > 0.0 {
+            reflect_probability * cos_theta / PI
+        } else {
+            (1.0 - reflect_probability) * -cos_theta / PI
+        }
+    }
+}
+
+/// A base material under a clear dielectric coating, such as varnish or car paint clear coat.
+pub struct Coated {
+    base: Arc<dyn Material>,
+    index_of_refraction: f64,
+    roughness: f64,
+    thickness: f64,
+    absorption: Color,
+}
+
+impl Coated {
+    pub fn new(
+        base: Arc<dyn Material>,
+        index_of_refraction: f64,
+        roughness: f64,
+        thickness: f64,
+        absorption: Color,
+    ) -> Self {
+        Self {
+            base,
+            index_of_refraction,
+            roughness,
+            thickness,
+            absorption,
+        }
+    }
+}
+
+impl Material for Coated {
+    fn emitted(&self, in_ray: &Ray, hit_record: &HitRecord) -> Color {
+        self.base.emitted(in_ray, hit_record)
+    }
+
+    fn scatter(&self, in_ray: &Ray, hit_record: &HitRecord) -> Option<(Color, Ray, Lobe)> {
+        // The coating only covers the outside of the surface
+        if !hit_record.front_face {
+            return self.base.scatter(in_ray, hit_record);
+        }
+
+        scatter_coated(
+            in_ray,
+            hit_record,
+            self.base.as_ref(),
+            &self.distribution(),
+            self.index_of_refraction,
+            self.thickness * self.absorption,
+        )
+    }
+
+    fn eval(&self, in_ray: &Ray, hit_record: &HitRecord, direction: DVec3) -> Color {
+        if !hit_record.front_face {
+            return self.base.eval(in_ray, hit_record, direction);
+        }
+
+        let onb = Onb::from_w(hit_record.normal);
+        let wo = onb.to_local(-in_ray.direction.normalize());
+        let wi = onb.to_local(direction);
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+
+        // Reflection off the coating, plus the base seen through it and attenuated by the
+        // absorption along the refracted paths in and out of the layer. Light scattered up by
+        // the base is partly reflected back down by the coating, which a geometric series over
+        // the albedo of the base and the diffuse reflectance of the inside of the coating sums.
+        let coat =
+            eval_microfacet_dielectric(wo, wi, &self.distribution(), self.index_of_refraction);
+        let albedo = PI * luminance(self.base.eval(in_ray, hit_record, hit_record.normal));
+        let internal_reflectance =
+            (albedo * fresnel_diffuse(self.index_of_refraction)).clamp(0.0, 0.95);
+        let base = self.base.eval(in_ray, hit_record, direction)
+            * self.layer_transmittance(wo.z)
+            * self.layer_transmittance(wi.z)
+            / (self.index_of_refraction.powi(2) * (1.0 - internal_reflectance));
+
+        Color::ONE * coat + base
+    }
+
+    fn pdf(&self, in_ray: &Ray, hit_record: &HitRecord, direction: DVec3) -> f64 {
+        if !hit_record.front_face {
+            return self.base.pdf(in_ray, hit_record, direction);
+        }
+
+        let onb = Onb::from_w(hit_record.normal);
+        let wo = onb.to_local(-in_ray.direction.normalize());
+        let wi = onb.to_local(direction);
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+
+        // The coating reflects with its Fresnel reflectance and otherwise lets the base scatter
+        let coat =
+            pdf_microfacet_dielectric(wo, wi, &self.distribution(), self.index_of_refraction);
+        let reflectance = fresnel_dielectric(wo.z, self.index_of_refraction);
+
+        coat + (1.0 - reflectance) * self.base.pdf(in_ray, hit_record, direction)
+    }
+
+    fn is_evaluable(&self) -> bool {
+        // `eval` and `pdf` only approximate the random walk through the layer
+        false
+    }
+}
+
+impl Coated {
+    fn distribution(&self) -> MicrofacetDistribution {
+        MicrofacetDistribution::trowbridge_reitz(MicrofacetDistribution::roughness_to_alpha(
+            self.roughness,
+        ))
+    }
+
+    /// Returns the fraction of light passing through the coating at the given cosine on the
+    /// outside, after Fresnel reflection and absorption along the refracted path in the layer.
+    fn layer_transmittance(&self, cos_theta: f64) -> Color {
+        let sin2_theta_t = (1.0 - cos_theta * cos_theta) / self.index_of_refraction.powi(2);
+        let cos_theta_t = (1.0 - sin2_theta_t).max(1e-4).sqrt();
+
+        (1.0 - fresnel_dielectric(cos_theta, self.index_of_refraction))
+            * (-self.thickness * self.absorption / cos_theta_t).exp()
+    }
+}
+
+/// Blends two materials by a scalar `factor` texture, where 0 gives only `first` and 1 gives
+/// only `second`. One of the two is picked stochastically at each hit.
+pub struct Mix {
+    first: Arc<dyn Material>,
+    second: Arc<dyn Material>,
+    factor: Arc<dyn Texture>,
+}
+
+impl Mix {
+    pub fn new(
+        first: Arc<dyn Material>,
+        second: Arc<dyn Material>,
+        factor: Arc<dyn Texture>,
+    ) -> Self {
+        Self {
+            first,
+            second,
+            factor,
+        }
+    }
+
+    pub fn from_factor(first: Arc<dyn Material>, second: Arc<dyn Material>, factor: f64) -> Self {
+        Self::new(
+            first,
+            second,
+            Arc::new(SolidColor::from_rgb(factor, factor, factor)),
+        )
+    }
+
+    fn factor(&self, u: f64, v: f64, point: &Point3) -> f64 {
+        clamp(self.factor.scalar_value(u, v, point), 0.0, 1.0)
+    }
+}
+
+impl Material for Mix {
+    fn emitted(&self, in_ray: &Ray, hit_record: &HitRecord) -> Color {
+        let factor = self.factor(hit_record.u, hit_record.v, &hit_record.point);
+
+        self.first
+            .emitted(in_ray, hit_record)
+            .lerp(self.second.emitted(in_ray, hit_record), factor)
+    }
+
+    fn scatter(&self, in_ray: &Ray, hit_record: &HitRecord) -> Option<(Color, Ray, Lobe)> {
+        let factor = self.factor(hit_record.u, hit_record.v, &hit_record.point);
+
+        if random_f64() < factor {
+            self.second.scatter(in_ray, hit_record)
+        } else {
+            self.first.scatter(in_ray, hit_record)
+        }
+    }
+
+    fn eval(&self, in_ray: &Ray, hit_record: &HitRecord, direction: DVec3) -> Color {
+        let factor = self.factor(hit_record.u, hit_record.v, &hit_record.point);
+
+        self.first
+            .eval(in_ray, hit_record, direction)
+            .lerp(self.second.eval(in_ray, hit_record, direction), factor)
+    }
+
+    fn pdf(&self, in_ray: &Ray, hit_record: &HitRecord, direction: DVec3) -> f64 {
+        let factor = self.factor(hit_record.u, hit_record.v, &hit_record.point);
+
+        (1.0 - factor) * self.first.pdf(in_ray, hit_record, direction)
+            + factor * self.second.pdf(in_ray, hit_record, direction)
+    }
+
+    fn is_evaluable(&self) -> bool {
+        self.first.is_evaluable() && self.second.is_evaluable()
+    }
+}
+
+/// Adds two materials together, e.g. an emitter on top of a reflective surface. Emission is
+/// summed and scattering picks either material with equal probability.
+pub struct Additive {
+    first: Arc<dyn Material>,
+    second: Arc<dyn Material>,
+}
+
+impl Additive {
+    pub fn new(first: Arc<dyn Material>, second: Arc<dyn Material>) -> Self {
+        Self { first, second }
+    }
+}
+
+impl Material for Additive {
+    fn emitted(&self, in_ray: &Ray, hit_record: &HitRecord) -> Color {
+        self.first.emitted(in_ray, hit_record) + self.second.emitted(in_ray, hit_record)
+    }
+
+    fn scatter(&self, in_ray: &Ray, hit_record: &HitRecord) -> Option<(Color, Ray, Lobe)> {
+        let chosen = if random_f64() < 0.5 {
+            &self.first
+        } else {
+            &self.second
+        };
+
+        // Each material is picked half of the time, so its contribution counts twice
+        chosen
+            .scatter(in_ray, hit_record)
+            .map(|(attenuation, scattered_ray, lobe)| (2.0 * attenuation, scattered_ray, lobe))
+    }
+
+    fn eval(&self, in_ray: &Ray, hit_record: &HitRecord, direction: DVec3) -> Color {
+        self.first.eval(in_ray, hit_record, direction)
+            + self.second.eval(in_ray, hit_record, direction)
+    }
+
+    fn pdf(&self, in_ray: &Ray, hit_record: &HitRecord, direction: DVec3) -> f64 {
+        0.5 * (self.first.pdf(in_ray, hit_record, direction)
+            + self.second.pdf(in_ray, hit_record, direction))
+    }
+
+    fn is_evaluable(&self) -> bool {
+        self.first.is_evaluable() && self.second.is_evaluable()
+    }
+}
+
+/// Perturbs the shading normal of a base material with a tangent-space normal map, such as an
+/// `ImageTexture`, whose colors encode the normal as `2 * color - 1` with +Z pointing outwards.
+pub struct NormalMap {
+    base: Arc<dyn Material>,
+    normal_map: Arc<dyn Texture>,
+}
+
+impl NormalMap {
+    pub fn new(base: Arc<dyn Material>, normal_map: Arc<dyn Texture>) -> Self {
+        Self { base, normal_map }
+    }
+
+    pub fn from_image(base: Arc<dyn Material>, path: &Path) -> Self {
+        Self::new(base, Arc::new(ImageTexture::new(path)))
+    }
+}
+
+impl NormalMap {
+    fn shading_record(&self, in_ray: &Ray, hit_record: &HitRecord) -> HitRecord {
+        let outward_normal = if hit_record.front_face {
+            hit_record.normal
+        } else {
+            -hit_record.normal
+        };
+
+        // Tangent frame following the texture coordinates, or an arbitrary one without them
+        let tangent = hit_record.dpdu - outward_normal * outward_normal.dot(hit_record.dpdu);
+        let onb = if near_zero(&tangent) {
+            Onb::from_w(outward_normal)
+        } else {
+            let u = tangent.normalize();
+            let mut v = outward_normal.cross(u);
+            if v.dot(hit_record.dpdv) < 0.0 {
+                v = -v;
+            }
+            Onb {
+                u,
+                v,
+                w: outward_normal,
+            }
+        };
+
+        let encoded = self
+            .normal_map
+            .value(hit_record.u, hit_record.v, &hit_record.point);
+        let normal = onb.local(2.0 * encoded - Color::ONE);
+
+        perturb_normal(in_ray, hit_record, normal)
+    }
+}
+
+impl Material for NormalMap {
+    fn emitted(&self, in_ray: &Ray, hit_record: &HitRecord) -> Color {
+        self.base.emitted(in_ray, hit_record)
+    }
+
+    fn scatter(&self, in_ray: &Ray, hit_record: &HitRecord) -> Option<(Color, Ray, Lobe)> {
+        self.base
+            .scatter(in_ray, &self.shading_record(in_ray, hit_record))
+    }
+
+    fn eval(&self, in_ray: &Ray, hit_record: &HitRecord, direction: DVec3) -> Color {
+        self.base
+            .eval(in_ray, &self.shading_record(in_ray, hit_record), direction)
+    }
+
+    fn pdf(&self, in_ray: &Ray, hit_record: &HitRecord, direction: DVec3) -> f64 {
+        self.base
+            .pdf(in_ray, &self.shading_record(in_ray, hit_record), direction)
+    }
+
+    fn is_evaluable(&self) -> bool {
+        self.base.is_evaluable()
+    }
+}
+
+/// Perturbs the shading normal of a base material as if its surface were displaced along the
+/// normal by a scalar height texture scaled by `strength`.
+pub struct BumpMap {
+    base: Arc<dyn Material>,
+    height: Arc<dyn Texture>,
+    strength: f64,
+}
+
+impl BumpMap {
+    // Step in texture coordinates used to estimate the height derivatives
+    const DELTA: f64 = 5e-4;
+
+    pub fn new(base: Arc<dyn Material>, height: Arc<dyn Texture>, strength: f64) -> Self {
+        Self {
+            base,
+            height,
+            strength,
+        }
+    }
+
+    fn shading_record(&self, in_ray: &Ray, hit_record: &HitRecord) -> HitRecord {
+        let (u, v, point) = (hit_record.u, hit_record.v, hit_record.point);
+        let (dpdu, dpdv) = (hit_record.dpdu, hit_record.dpdv);
+        let outward_normal = if hit_record.front_face {
+            hit_record.normal
+        } else {
+            -hit_record.normal
+        };
+
+        // Bump maps need a parameterized surface
+        if near_zero(&dpdu.cross(dpdv)) {
+            return hit_record.clone();
+        }
+
+        // Forward differences of the height, moving the lookup point along with the coordinates
+        // so that solid textures such as noise work as well
+        let height = self.strength * self.height.scalar_value(u, v, &point);
+        let height_u = self.strength
+            * self
+                .height
+                .scalar_value(u + Self::DELTA, v, &(point + Self::DELTA * dpdu));
+        let height_v = self.strength
+            * self
+                .height
+                .scalar_value(u, v + Self::DELTA, &(point + Self::DELTA * dpdv));
+
+        let displaced_dpdu = dpdu + (height_u - height) / Self::DELTA * outward_normal;
+        let displaced_dpdv = dpdv + (height_v - height) / Self::DELTA * outward_normal;
+        let mut normal = displaced_dpdu.cross(displaced_dpdv).normalize();
+        if normal.dot(outward_normal) < 0.0 {
+            normal = -normal;
+        }
+
+        perturb_normal(in_ray, hit_record, normal)
+    }
+}
+
+impl Material for BumpMap {
+    fn emitted(&self, in_ray: &Ray, hit_record: &HitRecord) -> Color {
+        self.base.emitted(in_ray, hit_record)
+    }
+
+    fn scatter(&self, in_ray: &Ray, hit_record: &HitRecord) -> Option<(Color, Ray, Lobe)> {
+        self.base
+            .scatter(in_ray, &self.shading_record(in_ray, hit_record))
+    }
+
+    fn eval(&self, in_ray: &Ray, hit_record: &HitRecord, direction: DVec3) -> Color {
+        self.base
+            .eval(in_ray, &self.shading_record(in_ray, hit_record), direction)
+    }
+
+    fn pdf(&self, in_ray: &Ray, hit_record: &HitRecord, direction: DVec3) -> f64 {
+        self.base
+            .pdf(in_ray, &self.shading_record(in_ray, hit_record), direction)
+    }
+
+    fn is_evaluable(&self) -> bool {
+        self.base.is_evaluable()
+    }
+}
+
+/// An area light. The emission texture acts as a tint that is scaled by `intensity`, so a white
+/// tint emits a radiance equal to the intensity in the direction of the surface normal.
+pub struct DiffuseLight {
+    emit: Arc<dyn Texture>,
+    intensity: f64,
+    two_sided: bool,
+    focus: f64,
+    profile: Option<Arc<IesProfile>>,
+}
+
+impl DiffuseLight {
+    // Luminous efficacy used to convert photometric units, in lumens per watt
+    const LUMENS_PER_WATT: f64 = 683.0;
+
+    pub fn new(emit: Arc<dyn Texture>) -> Self {
+        Self {
+            emit,
+            intensity: 1.0,
+            two_sided: true,
+            focus: 0.0,
+            profile: None,
+        }
+    }
+
+    pub fn from_color(color: Color) -> Self {
+        Self::new(Arc::new(SolidColor::new(color)))
+    }
+
+    /// Creates a light with the color of a black body at the given temperature in kelvin.
+    pub fn from_temperature(kelvin: f64) -> Self {
+        Self::from_color(blackbody(kelvin))
+    }
+
+    /// Sets whether the light emits from both faces or only from the front face.
+    pub fn with_two_sided(mut self, two_sided: bool) -> Self {
+        self.two_sided = two_sided;
+        self
+    }
+
+    /// Sets the radiance in W/(sr m^2) emitted along the normal.
+    pub fn with_radiance(mut self, radiance: f64) -> Self {
+        self.intensity = radiance;
+        self
+    }
+
+    /// Sets the luminance in nits (cd/m^2) emitted along the normal.
+    pub fn with_luminance(self, nits: f64) -> Self {
+        self.with_radiance(nits / Self::LUMENS_PER_WATT)
+    }
+
+    /// Sets the total power in watts emitted by a light of the given surface area. Call this
+    /// after choosing the sidedness and focus, which both change how the power is spread.
+    pub fn with_power(self, watts: f64, area: f64) -> Self {
+        let sides = if self.two_sided { 2.0 } else { 1.0 };
+        let focus = self.focus;
+        // A cos^n emission profile radiates 2 pi / (n + 2) per unit area and unit radiance
+        self.with_radiance(watts * (focus + 2.0) / (2.0 * PI * area * sides))
+    }
+
+    /// Concentrates the emission around the normal with a cos^focus profile, like a soft
+    /// spotlight. A focus of zero gives a diffuse emitter.
+    pub fn with_focus(mut self, focus: f64) -> Self {
+        self.focus = focus.max(0.0);
+        self
+    }
+
+    /// Scales the emission by a photometric profile relative to its peak. The nadir of the
+    /// profile points along the normal and its horizontal angle of 0 along the u direction of
+    /// the surface, if it has one.
+    pub fn with_profile(mut self, profile: Arc<IesProfile>) -> Self {
+        self.profile = Some(profile);
+        self
+    }
+}
+
+impl Material for DiffuseLight {
+    fn emitted(&self, in_ray: &Ray, hit_record: &HitRecord) -> Color {
+        if !hit_record.front_face && !self.two_sided {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+
+        let mut intensity = self.intensity;
+        if self.focus > 0.0 {
+            let cos_theta = -in_ray.direction.normalize().dot(hit_record.normal);
+            intensity *= cos_theta.max(0.0).powf(self.focus);
+        }
+        if let Some(profile) = &self.profile {
+            let tangent =
+                hit_record.dpdu - hit_record.dpdu.dot(hit_record.normal) * hit_record.normal;
+            let onb = if tangent.length_squared() > 0.0 {
+                let u = tangent.normalize();
+                Onb {
+                    u,
+                    v: hit_record.normal.cross(u),
+                    w: hit_record.normal,
                 }
+            } else {
+                Onb::from_w(hit_record.normal)
+            };
+            intensity *= profile.relative_intensity(onb.to_local(-in_ray.direction.normalize()));
+        }
+
+        intensity
+            * self
+                .emit
+                .value(hit_record.u, hit_record.v, &hit_record.point)
+    }
+
+    fn eval(&self, _in_ray: &Ray, _hit_record: &HitRecord, _direction: DVec3) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    fn pdf(&self, _in_ray: &Ray, _hit_record: &HitRecord, _direction: DVec3) -> f64 {
+        0.0
+    }
+}
+
+pub struct Isotropic {
+    albedo: Arc<dyn Texture>,
+}
+
+impl Isotropic {
+    pub fn new(albedo: Arc<dyn Texture>) -> Self {
+        Self { albedo }
+    }
+
+    pub fn from_color(color: Color) -> Self {
+        Self {
+            albedo: Arc::new(SolidColor::new(color)),
+        }
+    }
+}
+
+impl Material for Isotropic {
+    fn scatter(&self, in_ray: &Ray, hit_record: &HitRecord) -> Option<(Color, Ray, Lobe)> {
+        let scattered_ray = Ray::new(hit_record.point, random_unit_vertor(), in_ray.time);
+        let attenuation = self
+            .albedo
+            .value(hit_record.u, hit_record.v, &hit_record.point);
+
+        Some((attenuation, scattered_ray, Lobe::Diffuse))
+    }
+
+    fn eval(&self, _in_ray: &Ray, hit_record: &HitRecord, _direction: DVec3) -> Color {
+        // The phase function is uniform over the sphere and has no cosine term
+        self.albedo
+            .value(hit_record.u, hit_record.v, &hit_record.point)
+            / (4.0 * PI)
+    }
+
+    fn pdf(&self, _in_ray: &Ray, _hit_record: &HitRecord, _direction: DVec3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+}
+
+/// Samples a microfacet normal from `distribution`, mirrors the incoming ray about it and weights
+/// the result by the BSDF times cosine over the sampling density. The shading frame follows the
+/// u direction of the surface, so that `alpha_x` is the roughness along u.
+fn scatter_microfacet_reflection(
+    in_ray: &Ray,
+    hit_record: &HitRecord,
+    distribution: &MicrofacetDistribution,
+    fresnel: impl Fn(f64) -> Color,
+) -> Option<(Color, Ray, Lobe)> {
+    let onb = Onb::from_w_and_tangent(hit_record.normal, hit_record.dpdu);
+    let wo = onb.to_local(-in_ray.direction.normalize());
+    if wo.z <= 0.0 {
+        return None;
+    }
+
+    let (wi, attenuation, lobe) = if distribution.effectively_smooth() {
+        (
+            DVec3::new(-wo.x, -wo.y, wo.z),
+            fresnel(wo.z),
+            Lobe::SpecularReflection,
+        )
+    } else {
+        let wm = distribution.sample_wm(wo);
+        let wi = reflect(-wo, wm);
+        if wi.z <= 0.0 {
+            return None;
+        }
+
+        let cos_theta_m = wo.dot(wm).abs();
+        let pdf = distribution.pdf(wo, wm) / (4.0 * cos_theta_m);
+        if pdf == 0.0 {
+            return None;
+        }
+        let f = distribution.d(wm) * fresnel(cos_theta_m) * distribution.g(wo, wi)
+            / (4.0 * wo.z * wi.z);
+
+        (wi, f * wi.z / pdf, Lobe::Glossy)
+    };
+
+    Some((
+        attenuation,
+        Ray::new(hit_record.point, onb.local(wi), in_ray.time),
+        lobe,
+    ))
+}
+
+/// Evaluates a microfacet reflection BSDF times cosine for the unit `direction`.
+fn eval_microfacet_reflection(
+    in_ray: &Ray,
+    hit_record: &HitRecord,
+    direction: DVec3,
+    distribution: &MicrofacetDistribution,
+    fresnel: impl Fn(f64) -> Color,
+) -> Color {
+    let onb = Onb::from_w_and_tangent(hit_record.normal, hit_record.dpdu);
+    let wo = onb.to_local(-in_ray.direction.normalize());
+    let wi = onb.to_local(direction);
+    if wo.z <= 0.0 || wi.z <= 0.0 || distribution.effectively_smooth() {
+        return Color::new(0.0, 0.0, 0.0);
+    }
+
+    let wm = (wo + wi).normalize();
+    distribution.d(wm) * fresnel(wo.dot(wm)) * distribution.g(wo, wi) / (4.0 * wo.z)
+}
+
+/// Returns the density with which `scatter_microfacet_reflection` generates the unit `direction`.
+fn pdf_microfacet_reflection(
+    in_ray: &Ray,
+    hit_record: &HitRecord,
+    direction: DVec3,
+    distribution: &MicrofacetDistribution,
+) -> f64 {
+    let onb = Onb::from_w_and_tangent(hit_record.normal, hit_record.dpdu);
+    let wo = onb.to_local(-in_ray.direction.normalize());
+    let wi = onb.to_local(direction);
+    if wo.z <= 0.0 || wi.z <= 0.0 || distribution.effectively_smooth() {
+        return 0.0;
+    }
+
+    let wm = (wo + wi).normalize();
+    distribution.pdf(wo, wm) / (4.0 * wo.dot(wm))
+}
+
+/// Evaluates the BSDF times cosine of a rough dielectric interface for the local directions `wo`
+/// and `wi`, covering both the reflection and the transmission that
+/// `scatter_microfacet_dielectric` samples.
+pub(crate) fn eval_microfacet_dielectric(
+    wo: DVec3,
+    wi: DVec3,
+    distribution: &MicrofacetDistribution,
+    eta: f64,
+) -> f64 {
+    if wo.z <= 0.0 || wi.z == 0.0 || distribution.effectively_smooth() {
+        return 0.0;
+    }
+
+    if wi.z > 0.0 {
+        let wm = (wo + wi).normalize();
+        let reflectance = fresnel_dielectric(wo.dot(wm), eta);
+
+        distribution.d(wm) * distribution.g(wo, wi) * reflectance / (4.0 * wo.z)
+    } else {
+        let Some((wm, denom)) = refraction_half_vector(wo, wi, eta) else {
+            return 0.0;
+        };
+        let cos_theta_om = wo.dot(wm);
+        let cos_theta_im = wi.dot(wm);
+        let transmittance = 1.0 - fresnel_dielectric(cos_theta_om, eta);
+
+        transmittance
+            * distribution.d(wm)
+            * distribution.g(wo, wi)
+            * (cos_theta_im * cos_theta_om / (wo.z * denom)).abs()
+    }
+}
+
+/// Returns the density with which `scatter_microfacet_dielectric` generates the local direction
+/// `wi`.
+pub(crate) fn pdf_microfacet_dielectric(
+    wo: DVec3,
+    wi: DVec3,
+    distribution: &MicrofacetDistribution,
+    eta: f64,
+) -> f64 {
+    if wo.z <= 0.0 || wi.z == 0.0 || distribution.effectively_smooth() {
+        return 0.0;
+    }
+
+    if wi.z > 0.0 {
+        let wm = (wo + wi).normalize();
+        let cos_theta_om = wo.dot(wm);
+        if cos_theta_om <= 0.0 {
+            return 0.0;
+        }
+
+        distribution.pdf(wo, wm) / (4.0 * cos_theta_om) * fresnel_dielectric(cos_theta_om, eta)
+    } else {
+        let Some((wm, denom)) = refraction_half_vector(wo, wi, eta) else {
+            return 0.0;
+        };
+        let transmittance = 1.0 - fresnel_dielectric(wo.dot(wm), eta);
+
+        distribution.pdf(wo, wm) * wi.dot(wm).abs() / denom * transmittance
+    }
+}
+
+/// Returns the generalized half vector of a refraction pair and the squared denominator of its
+/// Jacobian, or `None` if no microfacet can refract `wo` into `wi`. `eta` is the relative index
+/// of refraction of the side `wi` is on.
+pub(crate) fn refraction_half_vector(wo: DVec3, wi: DVec3, eta: f64) -> Option<(DVec3, f64)> {
+    let mut wm = (wo + eta * wi).normalize();
+    if wm.z < 0.0 {
+        wm = -wm;
+    }
+    if wm.is_nan() || wo.dot(wm) <= 0.0 || wi.dot(wm) >= 0.0 {
+        return None;
+    }
+
+    let denom = (wi.dot(wm) + wo.dot(wm) / eta).powi(2);
+    Some((wm, denom))
+}
+
+/// Performs a stochastic random walk between a dielectric coating and the base material below
+/// it, in the spirit of position-free Monte Carlo (Guo et al. 2018). Each passage through the
+/// layer is attenuated by Beer-Lambert absorption, where `optical_depth` is the absorption
+/// coefficient times the layer thickness.
+fn scatter_coated(
+    in_ray: &Ray,
+    hit_record: &HitRecord,
+    base: &dyn Material,
+    distribution: &MicrofacetDistribution,
+    index_of_refraction: f64,
+    optical_depth: Color,
+) -> Option<(Color, Ray, Lobe)> {
+    const MAX_LAYER_BOUNCES: usize = 32;
+
+    let normal = hit_record.normal;
+    let mut inner_record = hit_record.clone();
+    inner_record.normal = -normal;
+    let transmittance = |direction: DVec3| {
+        let cos_theta = direction.normalize().dot(normal).abs();
+        (-optical_depth / cos_theta).exp()
+    };
+
+    // The walk is a delta lobe only if every interaction along it is, and it counts as diffuse
+    // once the base scattered diffusely
+    let mut delta = true;
+    let mut diffuse = false;
+    let exit_lobe = |delta: bool, diffuse: bool, up: bool| match (up, delta) {
+        (true, true) => Lobe::SpecularReflection,
+        (true, false) if diffuse => Lobe::Diffuse,
+        (true, false) => Lobe::Glossy,
+        (false, specular) => Lobe::Transmission { specular },
+    };
+
+    // Enter the layer through the top interface, or reflect off the coating
+    let (mut throughput, mut ray, coat_lobe) =
+        scatter_microfacet_dielectric(in_ray, hit_record, distribution, index_of_refraction)?;
+    if ray.direction.dot(normal) > 0.0 {
+        return Some((throughput, ray, coat_lobe));
+    }
+    delta &= coat_lobe.is_delta();
+
+    for depth in 0..MAX_LAYER_BOUNCES {
+        // Travel down through the layer and scatter off the base
+        throughput *= transmittance(ray.direction);
+        let base_ray = Ray::new(hit_record.point - ray.direction, ray.direction, in_ray.time);
+        let (attenuation, scattered_ray, base_lobe) = base.scatter(&base_ray, hit_record)?;
+        throughput *= attenuation;
+        ray = scattered_ray;
+        delta &= base_lobe.is_delta();
+        diffuse |= base_lobe == Lobe::Diffuse;
+
+        // The base transmitted the ray out through the bottom of the layer
+        if ray.direction.dot(normal) <= 0.0 {
+            return Some((throughput, ray, exit_lobe(delta, diffuse, false)));
+        }
+
+        // Travel up through the layer and either leave through the coating or reflect back down
+        throughput *= transmittance(ray.direction);
+        let top_ray = Ray::new(hit_record.point - ray.direction, ray.direction, in_ray.time);
+        let (attenuation, scattered_ray, coat_lobe) = scatter_microfacet_dielectric(
+            &top_ray,
+            &inner_record,
+            distribution,
+            1.0 / index_of_refraction,
+        )?;
+        throughput *= attenuation;
+        ray = scattered_ray;
+        delta &= coat_lobe.is_delta();
+
+        if ray.direction.dot(normal) > 0.0 {
+            return Some((throughput, ray, exit_lobe(delta, diffuse, true)));
+        }
+
+        // Russian roulette once the walk has bounced a few times inside the layer
+        if depth > 3 {
+            let survival = f64::min(throughput.max_element(), 0.95);
+            if random_f64() >= survival {
+                return None;
             }
-            Material::Dielectric {
-                index_of_refraction,
-            } => {
-                let attenuation = Color::new(1.0, 1.0, 1.0);
-                let refraction_ratio = if hit_record.front_face {
-                    1.0 / index_of_refraction
-                } else {
-                    *index_of_refraction
-                };
-                let unit_direction = in_ray.direction.normalize();
+            throughput /= survival;
+        }
+    }
+
+    None
+}
 
-                let cos_theta = f64::min(-unit_direction.dot(hit_record.normal), 1.0);
-                let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+/// Samples reflection or transmission through a rough dielectric interface following
+/// Walter et al. 2007, choosing between the two proportionally to the Fresnel reflectance.
+pub(crate) fn scatter_microfacet_dielectric(
+    in_ray: &Ray,
+    hit_record: &HitRecord,
+    distribution: &MicrofacetDistribution,
+    eta: f64,
+) -> Option<(Color, Ray, Lobe)> {
+    let onb = Onb::from_w(hit_record.normal);
+    let wo = onb.to_local(-in_ray.direction.normalize());
+    if wo.z <= 0.0 {
+        return None;
+    }
 
-                let cant_refract = refraction_ratio * sin_theta > 1.0;
+    let (wi, attenuation, lobe) = if distribution.effectively_smooth() {
+        let wm = DVec3::new(0.0, 0.0, 1.0);
+        let (wi, lobe) = if fresnel_dielectric(wo.z, eta) > random_f64() {
+            (reflect(-wo, wm), Lobe::SpecularReflection)
+        } else {
+            (
+                refract(-wo, wm, 1.0 / eta),
+                Lobe::Transmission { specular: true },
+            )
+        };
 
-                let direction = if cant_refract
-                    || schlick_reflectance(cos_theta, refraction_ratio) > random_f64()
-                {
-                    reflect(unit_direction, hit_record.normal)
-                } else {
-                    refract(unit_direction, hit_record.normal, refraction_ratio)
-                };
+        (wi, Color::new(1.0, 1.0, 1.0), lobe)
+    } else {
+        let wm = distribution.sample_wm(wo);
+        let cos_theta_om = wo.dot(wm);
+        let pdf_wm = distribution.pdf(wo, wm);
+        if cos_theta_om <= 0.0 || pdf_wm == 0.0 {
+            return None;
+        }
 
-                let scattered_ray = Ray::new(hit_record.point, direction, in_ray.time);
-                Some((attenuation, scattered_ray))
+        let reflectance = fresnel_dielectric(cos_theta_om, eta);
+        let transmittance = 1.0 - reflectance;
+
+        if reflectance > random_f64() {
+            let wi = reflect(-wo, wm);
+            if wi.z <= 0.0 {
+                return None;
             }
-            Material::DiffuseLight { emit: _emit } => None,
-            Material::Isotropic { albedo } => {
-                let scattered_ray = Ray::new(hit_record.point, random_unit_vertor(), in_ray.time);
-                let attenuation = albedo.value(hit_record.u, hit_record.v, &hit_record.point);
 
-                Some((attenuation, scattered_ray))
+            let pdf = pdf_wm / (4.0 * cos_theta_om) * reflectance;
+            let f = distribution.d(wm) * distribution.g(wo, wi) * reflectance / (4.0 * wo.z * wi.z);
+
+            (wi, Color::ONE * f * wi.z / pdf, Lobe::Glossy)
+        } else {
+            let wi = refract(-wo, wm, 1.0 / eta);
+            if wi.z >= 0.0 {
+                return None;
             }
+
+            let cos_theta_im = wi.dot(wm);
+            let denom = (cos_theta_im + cos_theta_om / eta).powi(2);
+            let dwm_dwi = cos_theta_im.abs() / denom;
+            let pdf = pdf_wm * dwm_dwi * transmittance;
+            let f = transmittance
+                * distribution.d(wm)
+                * distribution.g(wo, wi)
+                * (cos_theta_im * cos_theta_om / (wi.z * wo.z * denom)).abs();
+
+            (
+                wi,
+                Color::ONE * f * wi.z.abs() / pdf,
+                Lobe::Transmission { specular: false },
+            )
         }
+    };
+
+    Some((
+        attenuation,
+        Ray::new(hit_record.point, onb.local(wi), in_ray.time),
+        lobe,
+    ))
+}
+
+/// Returns a copy of the hit record shaded with the given outward-facing normal. Normals that
+/// would face away from the incoming ray are rejected in favour of the geometric one.
+fn perturb_normal(in_ray: &Ray, hit_record: &HitRecord, outward_normal: DVec3) -> HitRecord {
+    let mut shading_record = hit_record.clone();
+    let outward_normal = outward_normal.normalize();
+    let normal = if hit_record.front_face {
+        outward_normal
+    } else {
+        -outward_normal
+    };
+
+    if !normal.is_nan() && normal.dot(in_ray.direction) < 0.0 {
+        shading_record.normal = normal;
     }
+    shading_record
 }
 
 /// Returns true if the vector is close to zero in all dimensions.
@@ -98,11 +1712,33 @@ fn near_zero(vector: &DVec3) -> bool {
         && (f64::abs(vector.z) < epsilon)
 }
 
-fn reflect(v: DVec3, n: DVec3) -> DVec3 {
+pub(crate) fn reflect(v: DVec3, n: DVec3) -> DVec3 {
     v - 2.0 * v.dot(n) * n
 }
 
-fn refract(uv: DVec3, n: DVec3, etai_over_etat: f64) -> DVec3 {
+/// Fresnel reflectance of a conductor with complex index of refraction `eta + i k`, seen from
+/// vacuum at the given cosine of the incident angle.
+fn fresnel_conductor(cos_theta_i: f64, eta: f64, k: f64) -> f64 {
+    let cos2_theta_i = clamp(cos_theta_i, 0.0, 1.0).powi(2);
+    let sin2_theta_i = 1.0 - cos2_theta_i;
+    let eta2 = eta * eta;
+    let k2 = k * k;
+
+    let t0 = eta2 - k2 - sin2_theta_i;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+    let t1 = a2_plus_b2 + cos2_theta_i;
+    let a = (0.5 * (a2_plus_b2 + t0)).sqrt();
+    let t2 = 2.0 * cos2_theta_i.sqrt() * a;
+    let r_s = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2_theta_i * a2_plus_b2 + sin2_theta_i * sin2_theta_i;
+    let t4 = t2 * sin2_theta_i;
+    let r_p = r_s * (t3 - t4) / (t3 + t4);
+
+    0.5 * (r_p + r_s)
+}
+
+pub(crate) fn refract(uv: DVec3, n: DVec3, etai_over_etat: f64) -> DVec3 {
     let cos_theta = f64::min(-uv.dot(n), 1.0);
     let r_out_perp = etai_over_etat * (uv + cos_theta * n);
     let r_out_parallel = -(f64::abs(1.0 - r_out_perp.length_squared())).sqrt() * n;
@@ -110,6 +1746,40 @@ fn refract(uv: DVec3, n: DVec3, etai_over_etat: f64) -> DVec3 {
     r_out_perp + r_out_parallel
 }
 
+// Schlick's approximation with a colored reflectance at normal incidence, used for conductors.
+pub(crate) fn schlick_fresnel(f0: Color, cosine: f64) -> Color {
+    f0 + (Color::new(1.0, 1.0, 1.0) - f0) * (1.0 - cosine).powi(5)
+}
+
+/// Unpolarized Fresnel reflectance of a dielectric interface, where `eta` is the ratio of the
+/// index of refraction on the transmitted side over the incident side.
+pub(crate) fn fresnel_dielectric(cos_theta_i: f64, eta: f64) -> f64 {
+    let (cos_theta_i, eta) = if cos_theta_i < 0.0 {
+        (-cos_theta_i, 1.0 / eta)
+    } else {
+        (cos_theta_i, eta)
+    };
+    let cos_theta_i = f64::min(cos_theta_i, 1.0);
+
+    let sin2_theta_t = (1.0 - cos_theta_i * cos_theta_i) / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        // Total internal reflection
+        return 1.0;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+
+    let r_parallel = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+    let r_perpendicular = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+    (r_parallel * r_parallel + r_perpendicular * r_perpendicular) / 2.0
+}
+
+/// Approximates the Fresnel reflectance of a dielectric interface averaged over a diffuse
+/// distribution of incident light, seen from the side with the higher index of refraction `eta`
+/// relative to the other side (Egan and Hilgeman 1973).
+pub(crate) fn fresnel_diffuse(eta: f64) -> f64 {
+    -1.440 / (eta * eta) + 0.710 / eta + 0.668 + 0.0636 * eta
+}
+
 // Use Schlick's approximation for reflectance.
 fn schlick_reflectance(cosine: f64, ref_idx: f64) -> f64 {
     let mut r0 = (1.0 - ref_idx) / (1.0 + ref_idx);
@@ -0,0 +1,188 @@
+use crate::random_f64;
+use glam::DVec3;
+use std::f64::consts::PI;
+
+// All directions are expressed in the local shading frame, where the surface normal is +Z and +X
+// follows the u direction of the surface where it has one.
+
+#[derive(Clone, Copy)]
+pub enum MicrofacetDistribution {
+    TrowbridgeReitz { alpha_x: f64, alpha_y: f64 },
+    Beckmann { alpha_x: f64, alpha_y: f64 },
+}
+
+impl MicrofacetDistribution {
+    pub fn trowbridge_reitz(alpha: f64) -> Self {
+        Self::TrowbridgeReitz {
+            alpha_x: alpha,
+            alpha_y: alpha,
+        }
+    }
+
+    pub fn beckmann(alpha: f64) -> Self {
+        Self::Beckmann {
+            alpha_x: alpha,
+            alpha_y: alpha,
+        }
+    }
+
+    /// Maps a perceptually linear roughness in [0, 1] to the distribution's alpha.
+    pub fn roughness_to_alpha(roughness: f64) -> f64 {
+        roughness * roughness
+    }
+
+    fn alphas(&self) -> (f64, f64) {
+        match *self {
+            Self::TrowbridgeReitz { alpha_x, alpha_y } => (alpha_x, alpha_y),
+            Self::Beckmann { alpha_x, alpha_y } => (alpha_x, alpha_y),
+        }
+    }
+
+    /// Returns true if the surface is so smooth that it should be treated as a perfect specular.
+    pub fn effectively_smooth(&self) -> bool {
+        let (alpha_x, alpha_y) = self.alphas();
+        f64::max(alpha_x, alpha_y) < 1e-3
+    }
+
+    /// Returns the differential area of microfacets oriented along `wm`.
+    pub fn d(&self, wm: DVec3) -> f64 {
+        let cos2_theta = wm.z * wm.z;
+        let sin2_theta = f64::max(0.0, 1.0 - cos2_theta);
+        let tan2_theta = sin2_theta / cos2_theta;
+        if tan2_theta.is_infinite() || tan2_theta.is_nan() {
+            return 0.0;
+        }
+        let cos4_theta = cos2_theta * cos2_theta;
+        let (alpha_x, alpha_y) = self.alphas();
+        let (cos2_phi, sin2_phi) = if sin2_theta == 0.0 {
+            (1.0, 0.0)
+        } else {
+            (wm.x * wm.x / sin2_theta, wm.y * wm.y / sin2_theta)
+        };
+        let e = tan2_theta * (cos2_phi / (alpha_x * alpha_x) + sin2_phi / (alpha_y * alpha_y));
+
+        match self {
+            Self::TrowbridgeReitz { .. } => {
+                1.0 / (PI * alpha_x * alpha_y * cos4_theta * (1.0 + e) * (1.0 + e))
+            }
+            Self::Beckmann { .. } => (-e).exp() / (PI * alpha_x * alpha_y * cos4_theta),
+        }
+    }
+
+    /// Smith's auxiliary function, measuring invisible masked microfacet area per visible area.
+    pub fn lambda(&self, w: DVec3) -> f64 {
+        let cos2_theta = w.z * w.z;
+        let sin2_theta = f64::max(0.0, 1.0 - cos2_theta);
+        let tan2_theta = sin2_theta / cos2_theta;
+        if tan2_theta.is_infinite() || tan2_theta.is_nan() {
+            return 0.0;
+        }
+        let (alpha_x, alpha_y) = self.alphas();
+        let alpha2 = if sin2_theta == 0.0 {
+            alpha_x * alpha_x
+        } else {
+            (w.x * w.x * alpha_x * alpha_x + w.y * w.y * alpha_y * alpha_y) / sin2_theta
+        };
+
+        match self {
+            Self::TrowbridgeReitz { .. } => ((1.0 + alpha2 * tan2_theta).sqrt() - 1.0) / 2.0,
+            Self::Beckmann { .. } => {
+                let a = 1.0 / (alpha2 * tan2_theta).sqrt();
+                if a >= 1.6 {
+                    0.0
+                } else {
+                    (1.0 - 1.259 * a + 0.396 * a * a) / (3.535 * a + 2.181 * a * a)
+                }
+            }
+        }
+    }
+
+    /// Smith masking function for a single direction.
+    pub fn g1(&self, w: DVec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// Height-correlated Smith masking-shadowing function.
+    pub fn g(&self, wo: DVec3, wi: DVec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Samples a microfacet normal as seen from `wo`.
+    ///
+    /// Trowbridge-Reitz samples the distribution of visible normals (Heitz 2018); Beckmann
+    /// samples `D(wm) cos(theta_m)` and flips the result into the hemisphere of `wo`.
+    pub fn sample_wm(&self, wo: DVec3) -> DVec3 {
+        let (alpha_x, alpha_y) = self.alphas();
+        let u_0 = random_f64();
+        let u_1 = random_f64();
+
+        match self {
+            Self::TrowbridgeReitz { .. } => {
+                // Transform wo to the hemispherical configuration
+                let mut wh = DVec3::new(alpha_x * wo.x, alpha_y * wo.y, wo.z).normalize();
+                if wh.z < 0.0 {
+                    wh = -wh;
+                }
+
+                // Find an orthonormal basis for the visible normal sampling
+                let t_1 = if wh.z < 0.99999 {
+                    DVec3::new(0.0, 0.0, 1.0).cross(wh).normalize()
+                } else {
+                    DVec3::new(1.0, 0.0, 0.0)
+                };
+                let t_2 = wh.cross(t_1);
+
+                // Generate a uniformly distributed point on the unit disk
+                let r = u_0.sqrt();
+                let phi = 2.0 * PI * u_1;
+                let p_x = r * phi.cos();
+                let mut p_y = r * phi.sin();
+
+                // Warp the hemispherical projection for visible normal sampling
+                let h = (1.0 - p_x * p_x).sqrt();
+                let s = (1.0 + wh.z) / 2.0;
+                p_y = (1.0 - s) * h + s * p_y;
+
+                // Reproject to the hemisphere and transform the normal back to the ellipsoid
+                let p_z = f64::max(0.0, 1.0 - p_x * p_x - p_y * p_y).sqrt();
+                let nh = p_x * t_1 + p_y * t_2 + p_z * wh;
+                DVec3::new(alpha_x * nh.x, alpha_y * nh.y, f64::max(1e-6, nh.z)).normalize()
+            }
+            Self::Beckmann { .. } => {
+                let log_sample = (1.0 - u_0).ln();
+                let (tan2_theta, phi) = if alpha_x == alpha_y {
+                    (-alpha_x * alpha_x * log_sample, 2.0 * PI * u_1)
+                } else {
+                    let mut phi = (alpha_y / alpha_x * (2.0 * PI * u_1 + 0.5 * PI).tan()).atan();
+                    if u_1 > 0.5 {
+                        phi += PI;
+                    }
+                    let (sin_phi, cos_phi) = phi.sin_cos();
+                    let tan2_theta = -log_sample
+                        / (cos_phi * cos_phi / (alpha_x * alpha_x)
+                            + sin_phi * sin_phi / (alpha_y * alpha_y));
+                    (tan2_theta, phi)
+                };
+
+                let cos_theta = 1.0 / (1.0 + tan2_theta).sqrt();
+                let sin_theta = f64::max(0.0, 1.0 - cos_theta * cos_theta).sqrt();
+                let wm = DVec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+                if wm.z * wo.z < 0.0 {
+                    -wm
+                } else {
+                    wm
+                }
+            }
+        }
+    }
+
+    /// Returns the density with which `sample_wm` generates `wm` for the given `wo`.
+    pub fn pdf(&self, wo: DVec3, wm: DVec3) -> f64 {
+        match self {
+            Self::TrowbridgeReitz { .. } => {
+                self.g1(wo) / wo.z.abs() * self.d(wm) * wo.dot(wm).abs()
+            }
+            Self::Beckmann { .. } => self.d(wm) * wm.z.abs(),
+        }
+    }
+}
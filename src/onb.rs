@@ -0,0 +1,51 @@
+use glam::DVec3;
+
+/// An orthonormal basis used as a local shading frame, with `w` along the surface normal.
+#[derive(Clone, Copy)]
+pub struct Onb {
+    pub u: DVec3,
+    pub v: DVec3,
+    pub w: DVec3,
+}
+
+impl Onb {
+    pub fn from_w(n: DVec3) -> Self {
+        let w = n.normalize();
+        let a = if w.x.abs() > 0.9 {
+            DVec3::new(0.0, 1.0, 0.0)
+        } else {
+            DVec3::new(1.0, 0.0, 0.0)
+        };
+        let v = w.cross(a).normalize();
+        let u = w.cross(v);
+
+        Self { u, v, w }
+    }
+
+    /// Builds a basis with `w` along `n` and `u` along the part of `tangent` perpendicular to it,
+    /// falling back to an arbitrary `u` if the tangent is zero or parallel to `n`.
+    pub fn from_w_and_tangent(n: DVec3, tangent: DVec3) -> Self {
+        let w = n.normalize();
+        let tangent = tangent - tangent.dot(w) * w;
+        if tangent.length_squared() < 1e-16 {
+            return Self::from_w(w);
+        }
+        let u = tangent.normalize();
+
+        Self {
+            u,
+            v: w.cross(u),
+            w,
+        }
+    }
+
+    /// Transforms a vector given in this basis into world space.
+    pub fn local(&self, a: DVec3) -> DVec3 {
+        a.x * self.u + a.y * self.v + a.z * self.w
+    }
+
+    /// Transforms a world space vector into this basis.
+    pub fn to_local(&self, a: DVec3) -> DVec3 {
+        DVec3::new(a.dot(self.u), a.dot(self.v), a.dot(self.w))
+    }
+}
@@ -0,0 +1,199 @@
+use crate::{camera::Camera, onb::Onb, random_f64_range, ray::Ray, Point3};
+use glam::DVec3;
+use std::f64::consts::PI;
+
+/// Builds the frame of a camera at `look_from` looking at `look_at`, with `u` to the right, `v`
+/// up and `w` pointing backwards.
+pub(crate) fn look_at_frame(look_from: Point3, look_at: Point3, vup: DVec3) -> Onb {
+    let w = (look_from - look_at).normalize();
+    let u = vup.cross(w).normalize();
+    let v = w.cross(u);
+
+    Onb { u, v, w }
+}
+
+/// A 360 by 180 degree latitude-longitude panorama, for baking environment maps and for VR
+/// viewers. The view direction is at the center of the image, longitude grows to the right and
+/// the top row looks along `vup`. Render it with an aspect ratio of 2.
+pub struct EquirectangularCamera {
+    pub look_from: Point3,
+    pub look_at: Point3,
+    pub vup: DVec3,
+
+    frame: Onb,
+}
+
+impl Default for EquirectangularCamera {
+    fn default() -> Self {
+        Self {
+            look_from: Point3::new(0.0, 0.0, 0.0),
+            look_at: Point3::new(0.0, 0.0, -1.0),
+            vup: DVec3::new(0.0, 1.0, 0.0),
+            frame: Onb::from_w(DVec3::Z),
+        }
+    }
+}
+
+impl EquirectangularCamera {
+    /// Returns the direction seen at the point `(s, t)` of the panorama.
+    pub fn direction(&self, s: f64, t: f64) -> DVec3 {
+        let longitude = 2.0 * PI * (s - 0.5);
+        let latitude = PI * (t - 0.5);
+
+        self.frame.local(DVec3::new(
+            latitude.cos() * longitude.sin(),
+            latitude.sin(),
+            -latitude.cos() * longitude.cos(),
+        ))
+    }
+}
+
+impl Camera for EquirectangularCamera {
+    fn init(&mut self, _aspect_ratio: f64) {
+        self.frame = look_at_frame(self.look_from, self.look_at, self.vup);
+    }
+
+    fn get_ray(&self, s: f64, t: f64) -> Option<Ray> {
+        Some(Ray::new(
+            self.look_from,
+            self.direction(s, t),
+            random_f64_range(0.0, 1.0),
+        ))
+    }
+}
+
+/// Renders the six 90 degree views along the axes of the camera frame side by side, in the order
+/// right, left, up, down, back and front, which is +X, -X, +Y, -Y, +Z and -Z for a camera
+/// looking down -Z with +Y up. Each face is the view from the inside of the cube, with the up and
+/// down faces turned so that they join the front face. Render it with an aspect ratio of 6.
+pub struct CubemapCamera {
+    pub look_from: Point3,
+    pub look_at: Point3,
+    pub vup: DVec3,
+
+    frame: Onb,
+}
+
+impl Default for CubemapCamera {
+    fn default() -> Self {
+        Self {
+            look_from: Point3::new(0.0, 0.0, 0.0),
+            look_at: Point3::new(0.0, 0.0, -1.0),
+            vup: DVec3::new(0.0, 1.0, 0.0),
+            frame: Onb::from_w(DVec3::Z),
+        }
+    }
+}
+
+impl CubemapCamera {
+    /// Returns the forward and up directions of a face in the camera frame.
+    fn face(index: usize) -> (DVec3, DVec3) {
+        match index {
+            0 => (DVec3::X, DVec3::Y),
+            1 => (-DVec3::X, DVec3::Y),
+            2 => (DVec3::Y, DVec3::Z),
+            3 => (-DVec3::Y, -DVec3::Z),
+            4 => (DVec3::Z, DVec3::Y),
+            _ => (-DVec3::Z, DVec3::Y),
+        }
+    }
+}
+
+impl Camera for CubemapCamera {
+    fn init(&mut self, _aspect_ratio: f64) {
+        self.frame = look_at_frame(self.look_from, self.look_at, self.vup);
+    }
+
+    fn get_ray(&self, s: f64, t: f64) -> Option<Ray> {
+        let face = ((6.0 * s) as usize).min(5);
+        let a = 2.0 * (6.0 * s - face as f64) - 1.0;
+        let b = 2.0 * t - 1.0;
+
+        let (forward, up) = Self::face(face);
+        let right = forward.cross(up);
+        let direction = self.frame.local(forward + a * right + b * up);
+
+        Some(Ray::new(
+            self.look_from,
+            direction,
+            random_f64_range(0.0, 1.0),
+        ))
+    }
+}
+
+/// How a fisheye lens maps the angle away from the view direction to the distance from the
+/// center of the image circle.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum FisheyeMapping {
+    /// The distance grows linearly with the angle
+    #[default]
+    Equidistant,
+    /// Equal areas of the image cover equal solid angles
+    Equisolid,
+}
+
+/// A fisheye lens with a circular image inscribed in the frame, covering a field of view `fov`
+/// in degrees of up to 360. The corners outside the circle stay black.
+pub struct FisheyeCamera {
+    pub fov: f64,
+    pub mapping: FisheyeMapping,
+
+    pub look_from: Point3,
+    pub look_at: Point3,
+    pub vup: DVec3,
+
+    frame: Onb,
+    aspect_ratio: f64,
+}
+
+impl Default for FisheyeCamera {
+    fn default() -> Self {
+        Self {
+            fov: 180.0,
+            mapping: FisheyeMapping::default(),
+            look_from: Point3::new(0.0, 0.0, 0.0),
+            look_at: Point3::new(0.0, 0.0, -1.0),
+            vup: DVec3::new(0.0, 1.0, 0.0),
+            frame: Onb::from_w(DVec3::Z),
+            aspect_ratio: 1.0,
+        }
+    }
+}
+
+impl Camera for FisheyeCamera {
+    fn init(&mut self, aspect_ratio: f64) {
+        self.frame = look_at_frame(self.look_from, self.look_at, self.vup);
+        self.aspect_ratio = aspect_ratio;
+    }
+
+    fn get_ray(&self, s: f64, t: f64) -> Option<Ray> {
+        // Position relative to the image circle, which touches the shorter sides of the frame
+        let (x, y) = if self.aspect_ratio >= 1.0 {
+            ((2.0 * s - 1.0) * self.aspect_ratio, 2.0 * t - 1.0)
+        } else {
+            (2.0 * s - 1.0, (2.0 * t - 1.0) / self.aspect_ratio)
+        };
+        let r = (x * x + y * y).sqrt();
+        if r > 1.0 {
+            return None;
+        }
+
+        let theta_max = self.fov.clamp(0.0, 360.0).to_radians() / 2.0;
+        let theta = match self.mapping {
+            FisheyeMapping::Equidistant => r * theta_max,
+            FisheyeMapping::Equisolid => 2.0 * (r * (theta_max / 2.0).sin()).asin(),
+        };
+        let phi = y.atan2(x);
+
+        let direction = self.frame.local(DVec3::new(
+            theta.sin() * phi.cos(),
+            theta.sin() * phi.sin(),
+            -theta.cos(),
+        ));
+        Some(Ray::new(
+            self.look_from,
+            direction,
+            random_f64_range(0.0, 1.0),
+        ))
+    }
+}
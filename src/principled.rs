@@ -0,0 +1,319 @@
+use crate::{
+    color::{luminance, Color},
+    hittable::HitRecord,
+    material::{
+        eval_microfacet_dielectric, fresnel_dielectric, pdf_microfacet_dielectric, reflect,
+        refract, refraction_half_vector, scatter_microfacet_dielectric, schlick_fresnel, Lobe,
+        Material,
+    },
+    microfacet::MicrofacetDistribution,
+    onb::Onb,
+    random_cosine_direction, random_f64,
+    ray::Ray,
+    texture::{SolidColor, Texture},
+};
+use glam::DVec3;
+use std::{f64::consts::PI, sync::Arc};
+
+/// A Disney-style principled BSDF combining diffuse, specular, metallic, sheen, clear coat and
+/// transmission lobes. Every parameter except `base_color` is read as a scalar texture.
+pub struct Principled {
+    pub base_color: Arc<dyn Texture>,
+    pub metallic: Arc<dyn Texture>,
+    pub roughness: Arc<dyn Texture>,
+    pub specular: Arc<dyn Texture>,
+    pub specular_tint: Arc<dyn Texture>,
+    pub sheen: Arc<dyn Texture>,
+    pub sheen_tint: Arc<dyn Texture>,
+    pub clearcoat: Arc<dyn Texture>,
+    pub clearcoat_gloss: Arc<dyn Texture>,
+    pub transmission: Arc<dyn Texture>,
+    pub subsurface: Arc<dyn Texture>,
+}
+
+impl Principled {
+    pub fn new(base_color: Arc<dyn Texture>) -> Self {
+        Self {
+            base_color,
+            metallic: constant(0.0),
+            roughness: constant(0.5),
+            specular: constant(0.5),
+            specular_tint: constant(0.0),
+            sheen: constant(0.0),
+            sheen_tint: constant(0.5),
+            clearcoat: constant(0.0),
+            clearcoat_gloss: constant(1.0),
+            transmission: constant(0.0),
+            subsurface: constant(0.0),
+        }
+    }
+
+    fn lobes(&self, hit_record: &HitRecord) -> PrincipledLobes {
+        let (u, v, point) = (hit_record.u, hit_record.v, &hit_record.point);
+        let scalar = |texture: &Arc<dyn Texture>| texture.scalar_value(u, v, point);
+
+        let base_color = self.base_color.value(u, v, point);
+        let metallic = scalar(&self.metallic);
+        let roughness = scalar(&self.roughness);
+        let specular = scalar(&self.specular);
+        let transmission = scalar(&self.transmission);
+
+        let base_luminance = luminance(base_color);
+        let tint = if base_luminance > 0.0 {
+            base_color / base_luminance
+        } else {
+            Color::ONE
+        };
+        let specular_color = specular * 0.08 * Color::ONE.lerp(tint, scalar(&self.specular_tint));
+        let sheen_color = scalar(&self.sheen) * Color::ONE.lerp(tint, scalar(&self.sheen_tint));
+
+        // Map the specular level to an index of refraction, 0.5 corresponds to 1.5
+        let eta = 2.0 / (1.0 - (0.08 * specular).sqrt()) - 1.0;
+
+        PrincipledLobes {
+            base_color,
+            specular_color: specular_color.lerp(base_color, metallic),
+            sheen_color,
+            roughness,
+            subsurface: scalar(&self.subsurface),
+            clearcoat: scalar(&self.clearcoat),
+            clearcoat_alpha: 0.1 + (0.001 - 0.1) * scalar(&self.clearcoat_gloss),
+            diffuse_weight: (1.0 - metallic) * (1.0 - transmission),
+            transmission_weight: (1.0 - metallic) * transmission,
+            eta,
+            distribution: MicrofacetDistribution::trowbridge_reitz(f64::max(
+                MicrofacetDistribution::roughness_to_alpha(roughness),
+                1e-3,
+            )),
+        }
+    }
+}
+
+impl Material for Principled {
+    fn scatter(&self, in_ray: &Ray, hit_record: &HitRecord) -> Option<(Color, Ray, Lobe)> {
+        let lobes = self.lobes(hit_record);
+
+        // A ray leaving the interior of a transmissive object only sees the dielectric interface
+        if !hit_record.front_face && lobes.transmission_weight > 0.0 {
+            return scatter_microfacet_dielectric(
+                in_ray,
+                hit_record,
+                &lobes.distribution,
+                1.0 / lobes.eta,
+            );
+        }
+
+        let onb = Onb::from_w(hit_record.normal);
+        let wo = onb.to_local(-in_ray.direction.normalize());
+        if wo.z <= 0.0 {
+            return None;
+        }
+
+        let (wi, lobe) = lobes.sample(wo)?;
+        let pdf = lobes.pdf(wo, wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+
+        Some((
+            lobes.eval(wo, wi) / pdf,
+            Ray::new(hit_record.point, onb.local(wi), in_ray.time),
+            lobe,
+        ))
+    }
+
+    fn eval(&self, in_ray: &Ray, hit_record: &HitRecord, direction: DVec3) -> Color {
+        let lobes = self.lobes(hit_record);
+        let onb = Onb::from_w(hit_record.normal);
+        let wo = onb.to_local(-in_ray.direction.normalize());
+        let wi = onb.to_local(direction);
+
+        if !hit_record.front_face && lobes.transmission_weight > 0.0 {
+            return Color::ONE
+                * eval_microfacet_dielectric(wo, wi, &lobes.distribution, 1.0 / lobes.eta);
+        }
+        if wo.z <= 0.0 {
+            return Color::ZERO;
+        }
+
+        lobes.eval(wo, wi)
+    }
+
+    fn pdf(&self, in_ray: &Ray, hit_record: &HitRecord, direction: DVec3) -> f64 {
+        let lobes = self.lobes(hit_record);
+        let onb = Onb::from_w(hit_record.normal);
+        let wo = onb.to_local(-in_ray.direction.normalize());
+        let wi = onb.to_local(direction);
+
+        if !hit_record.front_face && lobes.transmission_weight > 0.0 {
+            return pdf_microfacet_dielectric(wo, wi, &lobes.distribution, 1.0 / lobes.eta);
+        }
+        if wo.z <= 0.0 {
+            return 0.0;
+        }
+
+        lobes.pdf(wo, wi)
+    }
+}
+
+fn constant(value: f64) -> Arc<dyn Texture> {
+    Arc::new(SolidColor::from_rgb(value, value, value))
+}
+
+/// Principled parameters resolved at a single shading point. All directions are in the local
+/// shading frame with the normal along +Z.
+struct PrincipledLobes {
+    base_color: Color,
+    specular_color: Color,
+    sheen_color: Color,
+    roughness: f64,
+    subsurface: f64,
+    clearcoat: f64,
+    clearcoat_alpha: f64,
+    diffuse_weight: f64,
+    transmission_weight: f64,
+    eta: f64,
+    distribution: MicrofacetDistribution,
+}
+
+impl PrincipledLobes {
+    /// Probabilities of sampling the diffuse, specular, clear coat and transmission lobes.
+    fn lobe_probabilities(&self, wo: DVec3) -> [f64; 4] {
+        let weights = [
+            self.diffuse_weight * (luminance(self.base_color) + luminance(self.sheen_color)),
+            f64::max(luminance(schlick_fresnel(self.specular_color, wo.z)), 0.01),
+            0.25 * self.clearcoat * (0.04 + 0.96 * (1.0 - wo.z).powi(5)),
+            self.transmission_weight * luminance(self.base_color),
+        ];
+        let total: f64 = weights.iter().sum();
+
+        weights.map(|weight| weight / total)
+    }
+
+    fn sample(&self, wo: DVec3) -> Option<(DVec3, Lobe)> {
+        let probabilities = self.lobe_probabilities(wo);
+        let mut u = random_f64();
+        let mut lobe = 0;
+        while lobe < 3 && u >= probabilities[lobe] {
+            u -= probabilities[lobe];
+            lobe += 1;
+        }
+
+        match lobe {
+            0 => Some((random_cosine_direction(), Lobe::Diffuse)),
+            1 => Some((reflect(-wo, self.distribution.sample_wm(wo)), Lobe::Glossy)),
+            2 => Some((
+                reflect(-wo, sample_gtr1(self.clearcoat_alpha)),
+                Lobe::Glossy,
+            )),
+            _ => {
+                let wm = self.distribution.sample_wm(wo);
+                if fresnel_dielectric(wo.dot(wm), self.eta) >= 1.0 {
+                    return None;
+                }
+                Some((
+                    refract(-wo, wm, 1.0 / self.eta),
+                    Lobe::Transmission { specular: false },
+                ))
+            }
+        }
+    }
+
+    /// Returns the BSDF times the cosine of the scattered direction.
+    fn eval(&self, wo: DVec3, wi: DVec3) -> Color {
+        if wi.z > 0.0 {
+            let wh = (wo + wi).normalize();
+            let cos_theta_d = wi.dot(wh);
+            let fresnel_o = schlick_weight(wo.z);
+            let fresnel_i = schlick_weight(wi.z);
+
+            // Diffuse with retro-reflection and the Hanrahan-Krueger inspired subsurface term
+            let fd90 = 0.5 + 2.0 * self.roughness * cos_theta_d * cos_theta_d;
+            let fd = (1.0 + (fd90 - 1.0) * fresnel_i) * (1.0 + (fd90 - 1.0) * fresnel_o);
+            let fss90 = self.roughness * cos_theta_d * cos_theta_d;
+            let fss = (1.0 + (fss90 - 1.0) * fresnel_i) * (1.0 + (fss90 - 1.0) * fresnel_o);
+            let ss = 1.25 * (fss * (1.0 / (wi.z + wo.z) - 0.5) + 0.5);
+            let diffuse = self.base_color / PI * (fd + (ss - fd) * self.subsurface);
+            let sheen = self.sheen_color * schlick_weight(cos_theta_d);
+
+            let specular = self.distribution.d(wh)
+                * self.distribution.g(wo, wi)
+                * schlick_fresnel(self.specular_color, wo.dot(wh))
+                / (4.0 * wo.z * wi.z);
+
+            let clearcoat_distribution = MicrofacetDistribution::trowbridge_reitz(0.25);
+            let clearcoat = 0.25
+                * self.clearcoat
+                * gtr1(wh.z, self.clearcoat_alpha)
+                * clearcoat_distribution.g(wo, wi)
+                * (0.04 + 0.96 * schlick_weight(wo.dot(wh)))
+                / (4.0 * wo.z * wi.z);
+
+            (self.diffuse_weight * (diffuse + sheen) + specular + clearcoat) * wi.z
+        } else if wi.z < 0.0 && self.transmission_weight > 0.0 {
+            let Some((wm, denom)) = refraction_half_vector(wo, wi, self.eta) else {
+                return Color::ZERO;
+            };
+            let cos_theta_om = wo.dot(wm);
+            let cos_theta_im = wi.dot(wm);
+
+            let transmittance = 1.0 - fresnel_dielectric(cos_theta_om, self.eta);
+            let f = transmittance
+                * self.distribution.d(wm)
+                * self.distribution.g(wo, wi)
+                * (cos_theta_im * cos_theta_om / (wi.z * wo.z * denom)).abs();
+
+            self.transmission_weight * self.base_color * f * wi.z.abs()
+        } else {
+            Color::ZERO
+        }
+    }
+
+    /// Returns the density with which `sample` generates `wi`.
+    fn pdf(&self, wo: DVec3, wi: DVec3) -> f64 {
+        let [p_diffuse, p_specular, p_clearcoat, p_transmission] = self.lobe_probabilities(wo);
+
+        if wi.z > 0.0 {
+            let wh = (wo + wi).normalize();
+            let cos_theta_oh = wo.dot(wh);
+
+            p_diffuse * wi.z / PI
+                + p_specular * self.distribution.pdf(wo, wh) / (4.0 * cos_theta_oh)
+                + p_clearcoat * gtr1(wh.z, self.clearcoat_alpha) * wh.z / (4.0 * cos_theta_oh)
+        } else if wi.z < 0.0 && p_transmission > 0.0 {
+            let Some((wm, denom)) = refraction_half_vector(wo, wi, self.eta) else {
+                return 0.0;
+            };
+
+            p_transmission * self.distribution.pdf(wo, wm) * wi.dot(wm).abs() / denom
+        } else {
+            0.0
+        }
+    }
+}
+
+fn schlick_weight(cosine: f64) -> f64 {
+    (1.0 - cosine).clamp(0.0, 1.0).powi(5)
+}
+
+/// The Generalized-Trowbridge-Reitz distribution with gamma = 1 used by the clear coat lobe.
+fn gtr1(cos_theta_h: f64, alpha: f64) -> f64 {
+    if alpha >= 1.0 {
+        return 1.0 / PI;
+    }
+
+    let alpha2 = alpha * alpha;
+    let t = 1.0 + (alpha2 - 1.0) * cos_theta_h * cos_theta_h;
+    (alpha2 - 1.0) / (PI * alpha2.ln() * t)
+}
+
+fn sample_gtr1(alpha: f64) -> DVec3 {
+    let alpha2 = alpha * alpha;
+    let cos_theta = ((1.0 - alpha2.powf(1.0 - random_f64())) / (1.0 - alpha2))
+        .max(0.0)
+        .sqrt();
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * random_f64();
+
+    DVec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+}
@@ -1,11 +1,30 @@
 use crate::Point3;
 use glam::DVec3;
 
+/// What a ray is used for, so that objects can choose which kinds of rays see them.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum RayKind {
+    /// Rays leaving the camera
+    #[default]
+    Camera,
+    /// Rays testing the visibility of a light
+    Shadow,
+    /// Rays scattered by a perfectly specular reflection
+    Reflection,
+    /// Rays scattered by a glossy reflection off a rough surface
+    Glossy,
+    /// Rays scattered by a diffuse reflection
+    Diffuse,
+    /// Rays transmitted through a surface, whether refracted specularly or not
+    Transmission,
+}
+
 #[derive(Clone, Copy, Default)]
 pub struct Ray {
     pub origin: Point3,
     pub direction: DVec3,
     pub time: f64,
+    pub kind: RayKind,
 }
 
 impl Ray {
@@ -14,9 +33,15 @@ impl Ray {
             origin,
             direction,
             time,
+            kind: RayKind::Camera,
         }
     }
 
+    pub fn with_kind(mut self, kind: RayKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
     pub fn at(&self, t: f64) -> Point3 {
         // P(t) = A + tb
         // where
@@ -0,0 +1,467 @@
+use crate::{
+    camera::Camera, material::refract, onb::Onb, panoramic::look_at_frame, random_f64,
+    random_f64_range, ray::Ray, Point3,
+};
+use glam::DVec3;
+use std::{fs, path::Path};
+
+/// One spherical surface of a lens prescription, or the aperture stop when its curvature radius
+/// is zero. Lengths are in millimetres.
+#[derive(Clone, Copy)]
+struct LensInterface {
+    curvature_radius: f64,
+    // Distance along the axis to the next interface, or to the film for the last one
+    thickness: f64,
+    // Index of refraction of the medium behind the interface, zero for air at the stop
+    ior: f64,
+    aperture_radius: f64,
+}
+
+/// A camera that traces rays through the elements of a real lens, after the realistic camera of
+/// pbrt. Vignetting, distortion and the shift of the field of view with focus come out of the
+/// lens design instead of being modelled.
+///
+/// Lens files list one interface per line from the front of the lens to the back, each with its
+/// curvature radius, thickness, index of refraction and aperture diameter in millimetres, and
+/// with a zero radius for the aperture stop. Lines starting with `#` are comments. A double
+/// Gauss and a Cooke triplet, both of 50 mm focal length, are bundled in `assets/lenses`.
+pub struct RealisticCamera {
+    pub look_from: Point3,
+    pub look_at: Point3,
+    pub vup: DVec3,
+    // Diagonal of the film in millimetres
+    pub film_diagonal: f64,
+    // Distance in world units to the plane in focus
+    pub focus_dist: f64,
+    // Stops the aperture down to this diameter in millimetres, if smaller than in the lens file
+    pub aperture_diameter: Option<f64>,
+    // World units per millimetre, 0.001 for scenes modelled in metres
+    pub scale: f64,
+
+    prescription: Vec<LensInterface>,
+    interfaces: Vec<LensInterface>,
+    frame: Onb,
+    film_width: f64,
+    film_height: f64,
+    // Bounds of the exit pupil as seen from rings of the film at growing distance from the axis
+    exit_pupil_bounds: Vec<[f64; 4]>,
+    // Area of the largest of the exit pupil bounds
+    max_exit_pupil_area: f64,
+}
+
+impl RealisticCamera {
+    const EXIT_PUPIL_RINGS: usize = 64;
+    const EXIT_PUPIL_SAMPLES: usize = 16384;
+
+    pub fn new(path: &Path) -> Self {
+        let text = fs::read_to_string(path).expect("lens file not found");
+
+        Self::parse(&text).expect("invalid lens file")
+    }
+
+    /// Parses a lens prescription, returning `None` if it is malformed or empty.
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut prescription = Vec::new();
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let values = line
+                .split_whitespace()
+                .map(|value| value.parse::<f64>().ok())
+                .collect::<Option<Vec<_>>>()?;
+            let [curvature_radius, thickness, ior, aperture_diameter] = values[..] else {
+                return None;
+            };
+            prescription.push(LensInterface {
+                curvature_radius,
+                thickness,
+                ior,
+                aperture_radius: aperture_diameter / 2.0,
+            });
+        }
+
+        if prescription.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            look_from: Point3::new(0.0, 0.0, -1.0),
+            look_at: Point3::new(0.0, 0.0, 0.0),
+            vup: DVec3::new(0.0, 1.0, 0.0),
+            film_diagonal: 35.0,
+            focus_dist: 10.0,
+            aperture_diameter: None,
+            scale: 0.001,
+            interfaces: prescription.clone(),
+            prescription,
+            frame: Onb::from_w(DVec3::Z),
+            film_width: 0.0,
+            film_height: 0.0,
+            exit_pupil_bounds: Vec::new(),
+            max_exit_pupil_area: 0.0,
+        })
+    }
+
+    fn lens_rear_z(&self) -> f64 {
+        self.interfaces[self.interfaces.len() - 1].thickness
+    }
+
+    fn lens_front_z(&self) -> f64 {
+        self.interfaces
+            .iter()
+            .map(|interface| interface.thickness)
+            .sum()
+    }
+
+    fn rear_element_radius(&self) -> f64 {
+        self.interfaces[self.interfaces.len() - 1].aperture_radius
+    }
+
+    /// Traces a ray in camera space, with the film at the origin and the lens along +Z, from the
+    /// film out through the front of the lens. Returns `None` if the ray is blocked.
+    fn trace_lenses_from_film(&self, ray: &Ray) -> Option<Ray> {
+        // The lens runs along -Z in lens space
+        let mut origin = ray.origin * DVec3::new(1.0, 1.0, -1.0);
+        let mut direction = ray.direction * DVec3::new(1.0, 1.0, -1.0);
+        let mut element_z = 0.0;
+
+        for (i, interface) in self.interfaces.iter().enumerate().rev() {
+            element_z -= interface.thickness;
+            let (t, normal) = intersect_interface(interface, element_z, origin, direction)?;
+
+            origin += t * direction;
+            if origin.x * origin.x + origin.y * origin.y
+                > interface.aperture_radius * interface.aperture_radius
+            {
+                return None;
+            }
+
+            if let Some(normal) = normal {
+                let eta_i = interface.ior;
+                let eta_t = match i.checked_sub(1).map(|j| self.interfaces[j].ior) {
+                    Some(ior) if ior != 0.0 => ior,
+                    _ => 1.0,
+                };
+                direction = refract_checked(direction.normalize(), normal, eta_i / eta_t)?;
+            }
+        }
+
+        Some(Ray::new(
+            origin * DVec3::new(1.0, 1.0, -1.0),
+            direction * DVec3::new(1.0, 1.0, -1.0),
+            ray.time,
+        ))
+    }
+
+    /// Traces a ray in camera space from the scene in through the front of the lens towards the
+    /// film. Returns `None` if the ray is blocked.
+    fn trace_lenses_from_scene(&self, ray: &Ray) -> Option<Ray> {
+        let mut origin = ray.origin * DVec3::new(1.0, 1.0, -1.0);
+        let mut direction = ray.direction * DVec3::new(1.0, 1.0, -1.0);
+        let mut element_z = -self.lens_front_z();
+
+        for (i, interface) in self.interfaces.iter().enumerate() {
+            let (t, normal) = intersect_interface(interface, element_z, origin, direction)?;
+
+            origin += t * direction;
+            if origin.x * origin.x + origin.y * origin.y
+                > interface.aperture_radius * interface.aperture_radius
+            {
+                return None;
+            }
+
+            if let Some(normal) = normal {
+                let eta_i = match i.checked_sub(1).map(|j| self.interfaces[j].ior) {
+                    Some(ior) if ior != 0.0 => ior,
+                    _ => 1.0,
+                };
+                let eta_t = if interface.ior != 0.0 {
+                    interface.ior
+                } else {
+                    1.0
+                };
+                direction = refract_checked(direction.normalize(), normal, eta_i / eta_t)?;
+            }
+            element_z += interface.thickness;
+        }
+
+        Some(Ray::new(
+            origin * DVec3::new(1.0, 1.0, -1.0),
+            direction * DVec3::new(1.0, 1.0, -1.0),
+            ray.time,
+        ))
+    }
+
+    /// Finds the principal plane and focal point, along Z, of the thick lens that approximates
+    /// the lens system on the scene side and on the film side.
+    fn thick_lens_approximation(&self) -> Option<([f64; 2], [f64; 2])> {
+        // Trace rays parallel to the axis, close enough to it for the paraxial approximation
+        let x = 0.001 * self.film_diagonal;
+
+        let scene_ray = Ray::new(
+            Point3::new(x, 0.0, self.lens_front_z() + 1.0),
+            DVec3::new(0.0, 0.0, -1.0),
+            0.0,
+        );
+        let film_ray = self.trace_lenses_from_scene(&scene_ray)?;
+        let (p0, f0) = cardinal_points(&scene_ray, &film_ray);
+
+        let film_ray = Ray::new(
+            Point3::new(x, 0.0, self.lens_rear_z() - 1.0),
+            DVec3::new(0.0, 0.0, 1.0),
+            0.0,
+        );
+        let scene_ray = self.trace_lenses_from_film(&film_ray)?;
+        let (p1, f1) = cardinal_points(&film_ray, &scene_ray);
+
+        Some(([p0, p1], [f0, f1]))
+    }
+
+    /// Returns the distance between the rear element and the film that brings objects at the
+    /// given distance from the film into focus.
+    fn focus_thick_lens(&self, focus_distance: f64) -> Option<f64> {
+        let (pz, fz) = self.thick_lens_approximation()?;
+        let f = fz[0] - pz[0];
+        let z = -focus_distance;
+
+        let c = (pz[1] - z - pz[0]) * (pz[1] - z - 4.0 * f - pz[0]);
+        if c <= 0.0 {
+            return None;
+        }
+        let delta = 0.5 * (pz[1] - z + pz[0] - c.sqrt());
+
+        Some(self.lens_rear_z() + delta)
+    }
+
+    /// Bounds the points on the rear element through which light reaches the film between the
+    /// distances `x0` and `x1` from the axis.
+    fn bound_exit_pupil(&self, x0: f64, x1: f64) -> [f64; 4] {
+        let extent = 1.5 * self.rear_element_radius();
+        let mut bounds: Option<[f64; 4]> = None;
+
+        for i in 0..Self::EXIT_PUPIL_SAMPLES {
+            let film_x = x0 + (x1 - x0) * (i as f64 + 0.5) / Self::EXIT_PUPIL_SAMPLES as f64;
+            let rear = Point3::new(
+                extent * (2.0 * radical_inverse(2, i) - 1.0),
+                extent * (2.0 * radical_inverse(3, i) - 1.0),
+                self.lens_rear_z(),
+            );
+
+            let inside = bounds.is_some_and(|[min_x, min_y, max_x, max_y]| {
+                (min_x..=max_x).contains(&rear.x) && (min_y..=max_y).contains(&rear.y)
+            });
+            let film = Point3::new(film_x, 0.0, 0.0);
+            if inside
+                || self
+                    .trace_lenses_from_film(&Ray::new(film, rear - film, 0.0))
+                    .is_some()
+            {
+                bounds = Some(match bounds {
+                    Some([min_x, min_y, max_x, max_y]) => [
+                        min_x.min(rear.x),
+                        min_y.min(rear.y),
+                        max_x.max(rear.x),
+                        max_y.max(rear.y),
+                    ],
+                    None => [rear.x, rear.y, rear.x, rear.y],
+                });
+            }
+        }
+
+        // Grow the bounds by about the spacing of the samples, which may have missed the edges
+        match bounds {
+            Some([min_x, min_y, max_x, max_y]) => {
+                let margin = 2.0 * (2.0 * extent * 2.0_f64.sqrt())
+                    / (Self::EXIT_PUPIL_SAMPLES as f64).sqrt();
+                [
+                    min_x - margin,
+                    min_y - margin,
+                    max_x + margin,
+                    max_y + margin,
+                ]
+            }
+            None => [-extent, -extent, extent, extent],
+        }
+    }
+
+    /// Samples a point on the rear element within the exit pupil of the given film point,
+    /// returning it with the area of the bounds it was sampled from.
+    fn sample_exit_pupil(&self, film_x: f64, film_y: f64) -> (Point3, f64) {
+        let r_film = (film_x * film_x + film_y * film_y).sqrt();
+        let ring = ((r_film / (self.film_diagonal / 2.0) * Self::EXIT_PUPIL_RINGS as f64) as usize)
+            .min(Self::EXIT_PUPIL_RINGS - 1);
+        let [min_x, min_y, max_x, max_y] = self.exit_pupil_bounds[ring];
+        let x = random_f64_range(min_x, max_x);
+        let y = random_f64_range(min_y, max_y);
+
+        // The bounds were found along +X, so rotate them around to the film point
+        let (sin_theta, cos_theta) = if r_film != 0.0 {
+            (film_y / r_film, film_x / r_film)
+        } else {
+            (0.0, 1.0)
+        };
+
+        (
+            Point3::new(
+                cos_theta * x - sin_theta * y,
+                sin_theta * x + cos_theta * y,
+                self.lens_rear_z(),
+            ),
+            (max_x - min_x) * (max_y - min_y),
+        )
+    }
+}
+
+impl Camera for RealisticCamera {
+    fn init(&mut self, aspect_ratio: f64) {
+        self.frame = look_at_frame(self.look_from, self.look_at, self.vup);
+
+        self.film_height = self.film_diagonal / (1.0 + aspect_ratio * aspect_ratio).sqrt();
+        self.film_width = aspect_ratio * self.film_height;
+
+        self.interfaces = self.prescription.clone();
+        if let Some(aperture_diameter) = self.aperture_diameter {
+            for interface in self.interfaces.iter_mut() {
+                if interface.curvature_radius == 0.0 {
+                    interface.aperture_radius =
+                        interface.aperture_radius.min(aperture_diameter / 2.0);
+                }
+            }
+        }
+
+        if let Some(thickness) = self.focus_thick_lens(self.focus_dist / self.scale) {
+            let last = self.interfaces.len() - 1;
+            self.interfaces[last].thickness = thickness;
+        }
+
+        let ring_width = self.film_diagonal / 2.0 / Self::EXIT_PUPIL_RINGS as f64;
+        self.exit_pupil_bounds = (0..Self::EXIT_PUPIL_RINGS)
+            .map(|ring| {
+                self.bound_exit_pupil(ring as f64 * ring_width, (ring + 1) as f64 * ring_width)
+            })
+            .collect();
+        self.max_exit_pupil_area = self
+            .exit_pupil_bounds
+            .iter()
+            .map(|[min_x, min_y, max_x, max_y]| (max_x - min_x) * (max_y - min_y))
+            .fold(0.0, f64::max);
+    }
+
+    fn get_ray(&self, s: f64, t: f64) -> Option<Ray> {
+        // The lens turns the image upside down, so the film is flipped to get it upright
+        let film = Point3::new(
+            -(s - 0.5) * self.film_width,
+            -(t - 0.5) * self.film_height,
+            0.0,
+        );
+        let (rear, area) = self.sample_exit_pupil(film.x, film.y);
+        let ray = self.trace_lenses_from_film(&Ray::new(film, rear - film, 0.0))?;
+
+        // Light reaching the film falls off with cos^4 of its angle and with the area of the
+        // exit pupil. Rays are kept in proportion, relative to the largest bounds so that the
+        // probability never exceeds one, which darkens the whole image by the same factor
+        let cos_theta = (rear - film).normalize().z;
+        let weight = cos_theta.powi(4) * area / self.max_exit_pupil_area;
+        if random_f64() >= weight {
+            return None;
+        }
+
+        let frame = Onb {
+            u: self.frame.u,
+            v: self.frame.v,
+            w: -self.frame.w,
+        };
+        Some(Ray::new(
+            self.look_from + self.scale * frame.local(ray.origin),
+            frame.local(ray.direction),
+            random_f64_range(0.0, 1.0),
+        ))
+    }
+}
+
+/// Intersects a ray in lens space with an interface whose vertex is at `element_z`, returning
+/// the distance along the ray and, for a refracting surface, its normal facing the ray.
+fn intersect_interface(
+    interface: &LensInterface,
+    element_z: f64,
+    origin: Point3,
+    direction: DVec3,
+) -> Option<(f64, Option<DVec3>)> {
+    if interface.curvature_radius == 0.0 {
+        // The aperture stop is flat
+        if direction.z == 0.0 {
+            return None;
+        }
+        let t = (element_z - origin.z) / direction.z;
+        return (t >= 0.0).then_some((t, None));
+    }
+
+    let radius = interface.curvature_radius;
+    let z_center = element_z + radius;
+    let oc = origin - DVec3::new(0.0, 0.0, z_center);
+    let a = direction.length_squared();
+    let b = 2.0 * direction.dot(oc);
+    let c = oc.length_squared() - radius * radius;
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    let root = discriminant.sqrt();
+    let (t0, t1) = ((-b - root) / (2.0 * a), (-b + root) / (2.0 * a));
+
+    // Pick the side of the sphere that the lens surface lies on
+    let use_closer = (direction.z > 0.0) ^ (radius < 0.0);
+    let t = if use_closer { t0 } else { t1 };
+    if t < 0.0 {
+        return None;
+    }
+
+    let normal = (oc + t * direction).normalize();
+    let normal = if normal.dot(direction) > 0.0 {
+        -normal
+    } else {
+        normal
+    };
+    Some((t, Some(normal)))
+}
+
+/// Refracts a unit direction, returning `None` on total internal reflection.
+fn refract_checked(direction: DVec3, normal: DVec3, etai_over_etat: f64) -> Option<DVec3> {
+    let cos_theta = (-direction.dot(normal)).min(1.0);
+    let sin2_theta_t = etai_over_etat * etai_over_etat * (1.0 - cos_theta * cos_theta);
+    if sin2_theta_t >= 1.0 {
+        return None;
+    }
+
+    Some(refract(direction, normal, etai_over_etat))
+}
+
+/// Returns where, along Z, the principal plane and the focal point are for a ray parallel to the
+/// axis that enters a lens system as `ray_in` and leaves it as `ray_out`.
+fn cardinal_points(ray_in: &Ray, ray_out: &Ray) -> (f64, f64) {
+    let t_focus = -ray_out.origin.x / ray_out.direction.x;
+    let t_principal = (ray_in.origin.x - ray_out.origin.x) / ray_out.direction.x;
+
+    // The rays are in camera space, while the lens is laid out along -Z
+    (-ray_out.at(t_principal).z, -ray_out.at(t_focus).z)
+}
+
+/// Mirrors the digits of `index` in the given base around the decimal point, giving a low
+/// discrepancy sequence in [0, 1).
+fn radical_inverse(base: usize, mut index: usize) -> f64 {
+    let inverse_base = 1.0 / base as f64;
+    let mut factor = inverse_base;
+    let mut result = 0.0;
+
+    while index > 0 {
+        result += (index % base) as f64 * factor;
+        index /= base;
+        factor *= inverse_base;
+    }
+
+    result
+}
@@ -1,24 +1,37 @@
 use crate::{
-    camera::Camera,
+    background::{Background, SolidBackground},
+    camera::{Camera, ThinLensCamera},
     color::{write_color, Color},
-    hittable::{Hittable, HittableList},
+    hittable::{HitRecord, Hittable, HittableList},
     interval::Interval,
+    light::Light,
+    light_sampler::{LightSampler, LightSampling},
+    material::Lobe,
     random_f64,
-    ray::Ray,
+    ray::{Ray, RayKind},
 };
+use glam::DVec3;
 use indicatif::ProgressBar;
 use rayon::prelude::*;
-use std::time::Instant;
+use std::{collections::HashMap, sync::Arc, time::Instant};
 
 pub struct Scene {
     pub world: HittableList,
-    pub camera: Camera,
+    pub camera: Box<dyn Camera>,
     aspect_ratio: f64,
     image_width: u32,
     image_height: u32,
     pub samples_per_pixel: usize,
     pub max_depth: usize,
-    pub background_color: Color,
+    pub background: Arc<dyn Background>,
+    // Seen by rays leaving the camera in place of `background`, which still lights the scene
+    pub camera_background: Option<Arc<dyn Background>>,
+    pub lights: Vec<Arc<dyn Light>>,
+    pub light_sampling: LightSampling,
+    // Built from `lights` when rendering starts
+    light_sampler: Option<Box<dyn LightSampler>>,
+    // Index of each light in `lights` by its address, to find the lights that rays hit
+    light_indices: HashMap<usize, usize>,
 }
 
 impl Scene {
@@ -30,16 +43,25 @@ impl Scene {
     ) -> Self {
         Self {
             world: HittableList::default(),
-            camera: Camera::default(),
+            camera: Box::new(ThinLensCamera::default()),
             aspect_ratio,
             image_width,
             image_height: (image_width as f64 / aspect_ratio) as u32,
             samples_per_pixel,
             max_depth,
-            background_color: Color::new(0.0, 0.0, 0.0),
+            background: Arc::new(SolidBackground::new(Color::new(0.0, 0.0, 0.0))),
+            camera_background: None,
+            lights: Vec::new(),
+            light_sampling: LightSampling::default(),
+            light_sampler: None,
+            light_indices: HashMap::new(),
         }
     }
 
+    pub fn add_light(&mut self, light: Arc<dyn Light>) {
+        self.lights.push(light);
+    }
+
     pub fn set_aspect_ratio(&mut self, aspect_ratio: f64) {
         self.aspect_ratio = aspect_ratio;
         self.image_height = calculate_image_height(self.image_width, aspect_ratio);
@@ -53,6 +75,7 @@ impl Scene {
     pub fn render(&mut self) {
         let start = Instant::now();
         self.camera.init(self.aspect_ratio);
+        self.init_lights();
 
         println!("P3");
         println!("{} {}", self.image_width, self.image_height);
@@ -82,8 +105,9 @@ impl Scene {
                                 let s = (i as f64
                                     + (s_i as f64 + random_f64()) / sqrt_sample_per_pixel as f64)
                                     / (self.image_width as f64 - 1.0);
-                                let ray = self.camera.get_ray(s, t);
-                                pixel_color += self.ray_color(ray, self.max_depth);
+                                if let Some(ray) = self.camera.get_ray(s, t) {
+                                    pixel_color += self.ray_color(ray, self.max_depth, None);
+                                }
                             }
                         }
                         progress_bar.inc(1);
@@ -125,42 +149,247 @@ impl Scene {
         eprintln!("Took {:?} wall time", start.elapsed());
     }
 
-    fn ray_color(&self, ray: Ray, depth: usize) -> Color {
+    fn init_lights(&mut self) {
+        let scene_radius = if self.world.objects.is_empty() {
+            0.0
+        } else {
+            self.world.bounding_box().diagonal().length() / 2.0
+        };
+
+        self.light_sampler = if self.lights.is_empty() {
+            None
+        } else {
+            Some(self.light_sampling.build(&self.lights, scene_radius))
+        };
+        self.light_indices = self
+            .lights
+            .iter()
+            .enumerate()
+            .map(|(index, light)| (Arc::as_ptr(light) as *const () as usize, index))
+            .collect();
+    }
+
+    /// Returns the radiance arriving along the ray. `previous` is the hit record the ray was
+    /// scattered from with the lobe its material sampled the ray from and the density of the
+    /// material sampling the ray, which is zero if it could not have been sampled otherwise and
+    /// meaningless for delta lobes.
+    fn ray_color(
+        &self,
+        ray: Ray,
+        depth: usize,
+        previous: Option<(&HitRecord, Lobe, f64)>,
+    ) -> Color {
         // if we've exceeded the ray bounce limit, no more light is gathered.
         if depth == 0 {
             return Color::new(0.0, 0.0, 0.0);
         }
 
-        // if the ray hits noting, return the background_color
+        // if the ray hits noting, return the background
         if let Some(hitted_record) = self.world.hit(&ray, Interval::new(0.001, f64::MAX)) {
             // let mut scattered_ray = Ray::default();
             // let mut attenuation = Color::default();
-            let color_from_emission = hitted_record.material.emitted(
-                hitted_record.u,
-                hitted_record.v,
-                &hitted_record.point,
-            );
+            let mut color_from_emission = hitted_record.material.emitted(&ray, &hitted_record);
+            match (&hitted_record.light, previous) {
+                (Some(light), Some((previous_record, lobe, scatter_pdf))) => {
+                    color_from_emission *= self.emission_weight(
+                        light,
+                        previous_record,
+                        ray.direction,
+                        lobe,
+                        scatter_pdf,
+                    );
+                }
+                // Emissive objects outside the light list only light unlinked surfaces and
+                // surfaces linked away from specific lights
+                (None, Some((previous_record, ..)))
+                    if !illuminated_by_unlisted(previous_record) =>
+                {
+                    color_from_emission = Color::new(0.0, 0.0, 0.0);
+                }
+                _ => {}
+            }
 
-            if let Some((attenuation, scattered_ray)) =
+            // Direct light does not depend on the scattered ray, so it is gathered even when the
+            // material fails to sample one
+            let color_from_lights = self.sample_lights(&ray, &hitted_record);
+
+            if let Some((attenuation, scattered_ray, lobe)) =
                 hitted_record.material.scatter(&ray, &hitted_record)
             {
-                let color_from_scatter = attenuation * self.ray_color(scattered_ray, depth - 1);
+                // Light found by rays scattered off materials that cannot be evaluated is not
+                // sampled directly, so it is counted in full
+                let scatter_pdf = if hitted_record.material.is_evaluable() {
+                    hitted_record.material.pdf(
+                        &ray,
+                        &hitted_record,
+                        scattered_ray.direction.normalize(),
+                    )
+                } else {
+                    0.0
+                };
+                let scattered_ray = scattered_ray.with_kind(lobe.ray_kind());
+                let color_from_scatter = attenuation
+                    * self.ray_color(
+                        scattered_ray,
+                        depth - 1,
+                        Some((&hitted_record, lobe, scatter_pdf)),
+                    );
 
-                color_from_emission + color_from_scatter
+                color_from_emission + color_from_lights + color_from_scatter
             } else {
-                color_from_emission
+                color_from_emission + color_from_lights
+            }
+        } else {
+            let direction = ray.direction.normalize();
+            match &self.camera_background {
+                Some(camera_background) if ray.kind == RayKind::Camera => {
+                    camera_background.radiance(direction)
+                }
+                _ => {
+                    let scatter_pdf = match previous {
+                        // The background does not light surfaces linked to specific lights
+                        Some((previous_record, ..))
+                            if !illuminated_by_unlisted(previous_record) =>
+                        {
+                            return Color::new(0.0, 0.0, 0.0);
+                        }
+                        // Only rays scattered from a non-delta lobe compete with sampling the
+                        // background directly
+                        Some((_, lobe, scatter_pdf)) if !lobe.is_delta() => scatter_pdf,
+                        _ => 0.0,
+                    };
+                    let radiance = self.background.radiance(direction);
+                    let light_pdf = self.background.pdf(direction);
+                    if scatter_pdf > 0.0 && light_pdf > 0.0 {
+                        // Balance heuristic weight against sampling the background directly
+                        radiance * scatter_pdf / (scatter_pdf + light_pdf)
+                    } else {
+                        radiance
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Scene {
+    /// Estimates the direct light reaching the hit point from the background and from one light
+    /// picked from the light list by the light sampler. Materials that cannot be evaluated are
+    /// only lit this way by lights that rays cannot hit, using their approximate evaluation.
+    fn sample_lights(&self, ray: &Ray, hit_record: &HitRecord) -> Color {
+        let mut color = Color::new(0.0, 0.0, 0.0);
+        let evaluable = hit_record.material.is_evaluable();
+
+        if let Some((direction, radiance, light_pdf)) = self
+            .background
+            .sample(random_f64(), random_f64())
+            .filter(|_| evaluable && illuminated_by_unlisted(hit_record))
+        {
+            let bsdf = hit_record.material.eval(ray, hit_record, direction);
+            if bsdf != Color::ZERO && self.unoccluded(ray, hit_record, direction, f64::MAX) {
+                // Balance heuristic weight against the material sampling the same direction
+                let scatter_pdf = hit_record.material.pdf(ray, hit_record, direction);
+                color += bsdf * radiance / (light_pdf + scatter_pdf);
             }
+        }
+
+        let Some((index, pmf)) = self.light_sampler.as_ref().and_then(|light_sampler| {
+            light_sampler.sample(hit_record.point, hit_record.normal, random_f64())
+        }) else {
+            return color;
+        };
+
+        let light = &self.lights[index];
+        if let Some(light_links) = &hit_record.light_links {
+            if !light_links.illuminates(light) {
+                return color;
+            }
+        }
+
+        let Some(sample) = light
+            .sample(hit_record.point)
+            .filter(|sample| evaluable || sample.pdf == 0.0)
+        else {
+            return color;
+        };
+        let bsdf = hit_record.material.eval(ray, hit_record, sample.direction);
+        if bsdf == Color::ZERO
+            || !self.unoccluded(ray, hit_record, sample.direction, sample.distance)
+        {
+            return color;
+        }
+
+        if sample.pdf > 0.0 {
+            // Balance heuristic weight against the material sampling a ray that hits the light
+            let light_pdf = pmf * sample.pdf;
+            let scatter_pdf = hit_record.material.pdf(ray, hit_record, sample.direction);
+            color += bsdf * sample.radiance * sample.pdf / (light_pdf + scatter_pdf);
         } else {
-            self.background_color
+            color += bsdf * sample.radiance / pmf;
         }
 
-        // let unit_direction = ray.direction().normalize();
-        // let t = 0.5 * (unit_direction.y + 1.0);
+        color
+    }
 
-        // // lerp
-        // // blended_value = (1 - t) * start_value + t * end_value
-        // (1.0 - t) * Color::new(1.0, 1.0, 1.0) + t * Color::new(0.5, 0.7, 1.0)
+    /// Returns the balance heuristic weight for light emitted by a light in the light list and
+    /// found by a ray that was scattered from `previous` out of `lobe` with density
+    /// `scatter_pdf`, against next-event estimation sampling the same light there.
+    fn emission_weight(
+        &self,
+        light: &Arc<dyn Light>,
+        previous: &HitRecord,
+        direction: DVec3,
+        lobe: Lobe,
+        scatter_pdf: f64,
+    ) -> f64 {
+        // Lights linked away from a surface do not light it in either way
+        if let Some(light_links) = &previous.light_links {
+            if !light_links.illuminates(light) {
+                return 0.0;
+            }
+        }
+        // Next-event estimation cannot find rays from delta lobes, whatever the density of the
+        // material's other lobes in the same direction
+        if lobe.is_delta() || scatter_pdf == 0.0 {
+            return 1.0;
+        }
+
+        let (Some(light_sampler), Some(&index)) = (
+            &self.light_sampler,
+            self.light_indices
+                .get(&(Arc::as_ptr(light) as *const () as usize)),
+        ) else {
+            return 1.0;
+        };
+        let light_pdf = light_sampler.pmf(previous.point, previous.normal, index)
+            * light.pdf(previous.point, direction.normalize());
+
+        scatter_pdf / (scatter_pdf + light_pdf)
     }
+
+    /// Returns true if nothing blocks the way from the hit point along `direction` up to
+    /// `distance`.
+    fn unoccluded(
+        &self,
+        ray: &Ray,
+        hit_record: &HitRecord,
+        direction: DVec3,
+        distance: f64,
+    ) -> bool {
+        let shadow_ray = Ray::new(hit_record.point, direction, ray.time).with_kind(RayKind::Shadow);
+
+        self.world
+            .hit(&shadow_ray, Interval::new(0.001, distance - 0.001))
+            .is_none()
+    }
+}
+
+/// Returns true if emissive objects outside the light list and the background light the surface.
+fn illuminated_by_unlisted(hit_record: &HitRecord) -> bool {
+    hit_record
+        .light_links
+        .as_ref()
+        .is_none_or(|light_links| light_links.illuminates_unlisted())
 }
 
 fn calculate_image_height(image_width: u32, aspect_ratio: f64) -> u32 {
@@ -0,0 +1,183 @@
+use crate::{
+    background::Background,
+    color::{blackbody, xyz_to_rgb, Color},
+    environment::EnvironmentLight,
+    light::DirectionalLight,
+};
+use glam::DVec3;
+use std::f64::consts::PI;
+
+/// The analytic daylight sky model of Preetham, Shirley and Smits, driven by the position of the
+/// sun and the atmospheric turbidity (2 for a very clear sky, 10 for haze). Radiance is given in
+/// kcd/m^2 and the matching sun light in klx, scaled by `intensity`.
+///
+/// The sun disk itself is not part of the sky radiance. Add the light returned by `sun` to the
+/// scene's light list to get direct sunlight.
+pub struct PreethamSky {
+    sun_direction: DVec3,
+    turbidity: f64,
+    intensity: f64,
+    // Zenith luminance and chromaticity
+    zenith: [f64; 3],
+    // Perez distribution coefficients A to E for the luminance and both chromaticities
+    perez: [[f64; 5]; 3],
+}
+
+impl PreethamSky {
+    // Angular diameter of the sun in degrees
+    const SUN_ANGULAR_DIAMETER: f64 = 0.53;
+    // Illuminance of the sun above the atmosphere in klx
+    const SOLAR_ILLUMINANCE: f64 = 128.0;
+    // Effective temperature of the sun in kelvin
+    const SOLAR_TEMPERATURE: f64 = 5778.0;
+
+    /// Creates a sky with the sun at the given elevation above the horizon and azimuth, measured
+    /// from +X towards +Z, both in degrees.
+    pub fn new(elevation: f64, azimuth: f64, turbidity: f64) -> Self {
+        // The model does not hold for the sun below the horizon
+        let elevation = elevation.clamp(0.0, 90.0).to_radians();
+        let azimuth = azimuth.to_radians();
+        let t = turbidity.max(1.0);
+        let theta_s = PI / 2.0 - elevation;
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+
+        let theta = [theta_s.powi(3), theta_s.powi(2), theta_s, 1.0];
+        let polynomial = |coefficients: [[f64; 4]; 3]| {
+            let [t2, t1, t0] = coefficients.map(|row| {
+                row.iter()
+                    .zip(theta.iter())
+                    .map(|(coefficient, power)| coefficient * power)
+                    .sum::<f64>()
+            });
+            t * t * t2 + t * t1 + t0
+        };
+        let zenith_x = polynomial([
+            [0.00166, -0.00375, 0.00209, 0.0],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        ]);
+        let zenith_y = polynomial([
+            [0.00275, -0.00610, 0.00317, 0.0],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688],
+        ]);
+
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        // Normalize the zenith values by the Perez function at the zenith
+        let zenith = [zenith_luminance, zenith_x, zenith_y];
+        let zenith = std::array::from_fn(|i| zenith[i] / perez_function(perez[i], 0.0, theta_s));
+
+        Self {
+            sun_direction: DVec3::new(
+                elevation.cos() * azimuth.cos(),
+                elevation.sin(),
+                elevation.cos() * azimuth.sin(),
+            ),
+            turbidity: t,
+            intensity: 1.0,
+            zenith,
+            perez,
+        }
+    }
+
+    pub fn with_intensity(mut self, intensity: f64) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    /// Unit direction towards the sun.
+    pub fn sun_direction(&self) -> DVec3 {
+        self.sun_direction
+    }
+
+    /// Returns the sky radiance arriving from the unit `direction`, black below the horizon.
+    pub fn radiance(&self, direction: DVec3) -> Color {
+        if direction.y <= 0.0 {
+            return Color::ZERO;
+        }
+
+        let theta = direction.y.min(1.0).acos();
+        let gamma = direction.dot(self.sun_direction).clamp(-1.0, 1.0).acos();
+        let [luminance, x, y] =
+            std::array::from_fn(|i| self.zenith[i] * perez_function(self.perez[i], theta, gamma));
+
+        // Convert from xyY to RGB
+        let xyz = DVec3::new(x / y * luminance, luminance, (1.0 - x - y) / y * luminance);
+        self.intensity * xyz_to_rgb(xyz).max(Color::ZERO)
+    }
+
+    /// Returns the sun as a directional light, dimmed and reddened by the atmosphere.
+    pub fn sun(&self) -> DirectionalLight {
+        let irradiance = Self::SOLAR_ILLUMINANCE
+            * blackbody(Self::SOLAR_TEMPERATURE)
+            * self.sun_transmittance()
+            * self.intensity;
+
+        DirectionalLight::new(-self.sun_direction, irradiance)
+            .with_angular_diameter(Self::SUN_ANGULAR_DIAMETER)
+    }
+
+    /// Bakes the sky into an importance-sampled environment light of the given resolution.
+    pub fn environment(&self, width: usize, height: usize) -> EnvironmentLight {
+        EnvironmentLight::from_fn(width, height, |direction| self.radiance(direction))
+    }
+
+    /// Transmittance of the atmosphere along the path of sunlight for the red, green and blue
+    /// wavelengths, from Rayleigh scattering and aerosol extinction.
+    fn sun_transmittance(&self) -> Color {
+        // Relative optical air mass for the zenith angle of the sun (Kasten 1966)
+        let theta_s = self.sun_direction.y.clamp(0.0, 1.0).acos();
+        let air_mass = 1.0 / (theta_s.cos() + 0.15 * (93.885 - theta_s.to_degrees()).powf(-1.253));
+
+        // Angstrom turbidity coefficients for the aerosols
+        let beta = 0.04608 * self.turbidity - 0.04586;
+        let alpha = 1.3;
+
+        // Wavelengths in micrometres
+        Color::from([0.650, 0.532, 0.450].map(|wavelength: f64| {
+            let rayleigh = (-0.008735 * wavelength.powf(-4.08) * air_mass).exp();
+            let aerosol = (-beta * wavelength.powf(-alpha) * air_mass).exp();
+            rayleigh * aerosol
+        }))
+    }
+}
+
+impl Background for PreethamSky {
+    fn radiance(&self, direction: DVec3) -> Color {
+        self.radiance(direction)
+    }
+}
+
+/// The Perez sky luminance distribution for a zenith angle `theta` and an angle `gamma` away
+/// from the sun.
+fn perez_function([a, b, c, d, e]: [f64; 5], theta: f64, gamma: f64) -> f64 {
+    let cos_gamma = gamma.cos();
+
+    (1.0 + a * (b / theta.cos().max(0.01)).exp())
+        * (1.0 + c * (d * gamma).exp() + e * cos_gamma * cos_gamma)
+}
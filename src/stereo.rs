@@ -0,0 +1,150 @@
+use crate::{
+    camera::{Camera, Projection, ThinLensCamera},
+    panoramic::EquirectangularCamera,
+    random_f64_range,
+    ray::Ray,
+};
+
+/// Where the two eye images go in the frame.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum StereoLayout {
+    /// The left eye on the left half and the right eye on the right half
+    #[default]
+    SideBySide,
+    /// The left eye on the top half and the right eye on the bottom half
+    TopBottom,
+}
+
+enum StereoEyes {
+    Projective {
+        camera: Box<ThinLensCamera>,
+        eyes: Box<[ThinLensCamera; 2]>,
+    },
+    Omnidirectional(EquirectangularCamera),
+}
+
+/// Renders the views of the left and right eye next to each other in one image, for VR headsets
+/// and 3D displays. The eyes sit `interocular_distance` apart around the position of the wrapped
+/// camera and their views converge at `convergence_distance`, where objects appear at the depth
+/// of the screen.
+pub struct StereoCamera {
+    pub interocular_distance: f64,
+    pub convergence_distance: f64,
+    pub layout: StereoLayout,
+
+    eyes: StereoEyes,
+}
+
+impl StereoCamera {
+    // Average distance between human pupils, in metres
+    const INTEROCULAR_DISTANCE: f64 = 0.064;
+
+    /// Creates a pair of views of a perspective camera. The eyes look straight ahead along
+    /// parallel axes and converge by shifting their views, which avoids the vertical parallax of
+    /// toed-in cameras. The convergence distance starts out at the focus distance.
+    pub fn new(camera: ThinLensCamera) -> Self {
+        Self {
+            interocular_distance: Self::INTEROCULAR_DISTANCE,
+            convergence_distance: camera.focus_dist,
+            layout: StereoLayout::default(),
+            eyes: StereoEyes::Projective {
+                eyes: Box::new([camera.clone(), camera.clone()]),
+                camera: Box::new(camera),
+            },
+        }
+    }
+
+    /// Creates an omni-directional stereo panorama, where every column of the equirectangular
+    /// image is seen from eyes on a circle around the camera, turned to face along it. The
+    /// convergence distance starts out infinite, which keeps the rays of both eyes parallel.
+    pub fn omnidirectional(camera: EquirectangularCamera) -> Self {
+        Self {
+            interocular_distance: Self::INTEROCULAR_DISTANCE,
+            convergence_distance: f64::INFINITY,
+            layout: StereoLayout::TopBottom,
+            eyes: StereoEyes::Omnidirectional(camera),
+        }
+    }
+
+    pub fn with_layout(mut self, layout: StereoLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Maps a point of the frame to the eye whose image holds it, -1 for the left eye and 1 for
+    /// the right eye, and to the point within that image.
+    fn eye(&self, s: f64, t: f64) -> (f64, f64, f64) {
+        match self.layout {
+            StereoLayout::SideBySide if s < 0.5 => (-1.0, 2.0 * s, t),
+            StereoLayout::SideBySide => (1.0, 2.0 * s - 1.0, t),
+            StereoLayout::TopBottom if t >= 0.5 => (-1.0, s, 2.0 * t - 1.0),
+            StereoLayout::TopBottom => (1.0, s, 2.0 * t),
+        }
+    }
+}
+
+impl Camera for StereoCamera {
+    fn init(&mut self, aspect_ratio: f64) {
+        let eye_aspect_ratio = match self.layout {
+            StereoLayout::SideBySide => aspect_ratio / 2.0,
+            StereoLayout::TopBottom => aspect_ratio * 2.0,
+        };
+        let half_distance = self.interocular_distance / 2.0;
+        let convergence_distance = self.convergence_distance;
+
+        match &mut self.eyes {
+            StereoEyes::Projective { camera, eyes } => {
+                let w = (camera.look_from - camera.look_at).normalize();
+                let u = camera.vup.cross(w).normalize();
+                let viewport_width = match camera.projection {
+                    Projection::Perspective => {
+                        eye_aspect_ratio * 2.0 * (camera.vfov.to_radians() / 2.0).tan()
+                    }
+                    // Parallel rays have no parallax to converge
+                    Projection::Orthographic { .. } => f64::INFINITY,
+                };
+
+                for (eye, side) in eyes.iter_mut().zip([-1.0, 1.0]) {
+                    let offset = side * half_distance * u;
+                    *eye = ThinLensCamera::clone(camera);
+                    eye.look_from += offset;
+                    eye.look_at += offset;
+
+                    // Shift the view of each eye back towards the point straight ahead of the
+                    // camera at the convergence distance
+                    eye.horizontal_shift = camera.horizontal_shift
+                        - side * half_distance / (convergence_distance * viewport_width);
+                    eye.init(eye_aspect_ratio);
+                }
+            }
+            StereoEyes::Omnidirectional(camera) => camera.init(eye_aspect_ratio),
+        }
+    }
+
+    fn get_ray(&self, s: f64, t: f64) -> Option<Ray> {
+        let (side, s, t) = self.eye(s, t);
+
+        match &self.eyes {
+            StereoEyes::Projective { eyes, .. } => {
+                eyes[if side < 0.0 { 0 } else { 1 }].get_ray(s, t)
+            }
+            StereoEyes::Omnidirectional(camera) => {
+                let direction = camera.direction(s, t);
+
+                // The eye sits on the circle, to the side of the horizontal direction of this
+                // column of the panorama
+                let horizon = camera.direction(s, 0.5);
+                let up = camera.direction(s, 1.0);
+                let origin = camera.look_from
+                    + side * self.interocular_distance / 2.0 * horizon.cross(up).normalize();
+
+                let direction = if self.convergence_distance.is_finite() {
+                    camera.look_from + self.convergence_distance * direction - origin
+                } else {
+                    direction
+                };
+                Some(Ray::new(origin, direction, random_f64_range(0.0, 1.0)))
+            }
+        }
+    }
+}
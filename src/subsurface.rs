@@ -0,0 +1,177 @@
+use crate::{
+    aabb::Aabb,
+    color::Color,
+    hittable::{HitRecord, Hittable},
+    interval::Interval,
+    material::{fresnel_diffuse, scatter_microfacet_dielectric, Lobe, Material},
+    microfacet::MicrofacetDistribution,
+    random_f64, random_unit_vertor,
+    ray::Ray,
+};
+use glam::DVec3;
+use std::{f64::consts::PI, sync::Arc};
+
+/// Wraps a closed boundary so that light entering it scatters volumetrically inside, as in skin,
+/// marble, wax or milk. The materials of the boundary itself are ignored.
+pub struct Subsurface {
+    boundary: Arc<dyn Hittable>,
+    material: Arc<dyn Material>,
+}
+
+impl Subsurface {
+    pub fn new(
+        boundary: Arc<dyn Hittable>,
+        albedo: Color,
+        mean_free_path: Color,
+        index_of_refraction: f64,
+    ) -> Self {
+        Self {
+            boundary: boundary.clone(),
+            material: Arc::new(RandomWalkSubsurface {
+                boundary,
+                albedo,
+                sigma_t: Color::ONE / mean_free_path,
+                index_of_refraction,
+            }),
+        }
+    }
+}
+
+impl Hittable for Subsurface {
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<HitRecord> {
+        if let Some(mut hitted_record) = self.boundary.hit(ray, ray_t) {
+            hitted_record.material = self.material.clone();
+            Some(hitted_record)
+        } else {
+            None
+        }
+    }
+
+    fn bounding_box(&self) -> &Aabb {
+        self.boundary.bounding_box()
+    }
+}
+
+/// Random-walk subsurface scattering with a smooth dielectric boundary, per-channel single
+/// scattering `albedo` and extinction coefficient `sigma_t`, and an isotropic phase function.
+pub struct RandomWalkSubsurface {
+    boundary: Arc<dyn Hittable>,
+    albedo: Color,
+    sigma_t: Color,
+    index_of_refraction: f64,
+}
+
+impl RandomWalkSubsurface {
+    const MAX_STEPS: usize = 4096;
+
+    /// Approximates the total diffuse reflectance of a semi-infinite slab of the medium with the
+    /// dipole model of Jensen et al. 2001.
+    fn diffuse_reflectance(&self) -> Color {
+        let internal_reflectance = fresnel_diffuse(self.index_of_refraction);
+        let a = (1.0 + internal_reflectance) / (1.0 - internal_reflectance);
+
+        let reflectance = |albedo: f64| {
+            let s = (3.0 * (1.0 - albedo)).sqrt();
+            0.5 * albedo * (1.0 + (-4.0 / 3.0 * a * s).exp()) * (-s).exp()
+        };
+        Color::new(
+            reflectance(self.albedo.x),
+            reflectance(self.albedo.y),
+            reflectance(self.albedo.z),
+        )
+    }
+}
+
+impl Material for RandomWalkSubsurface {
+    fn scatter(&self, in_ray: &Ray, hit_record: &HitRecord) -> Option<(Color, Ray, Lobe)> {
+        let smooth = MicrofacetDistribution::trowbridge_reitz(0.0);
+        let eta = if hit_record.front_face {
+            self.index_of_refraction
+        } else {
+            1.0 / self.index_of_refraction
+        };
+
+        // Refract into the medium or reflect off the boundary
+        let (mut throughput, ray, lobe) =
+            scatter_microfacet_dielectric(in_ray, hit_record, &smooth, eta)?;
+        if ray.direction.dot(hit_record.normal) > 0.0 {
+            return Some((throughput, ray, lobe));
+        }
+        let mut ray = Ray::new(ray.origin, ray.direction.normalize(), ray.time);
+        // Only rays leaving the boundary need an offset to avoid re-hitting their own origin
+        let mut t_min = 1e-4;
+
+        for step in 0..Self::MAX_STEPS {
+            // Sample the free-flight distance using a randomly chosen color channel and weight the
+            // result with the balance heuristic over all three channels
+            let channel = (random_f64() * 3.0) as usize;
+            let distance = -(1.0 - random_f64()).ln() / self.sigma_t[channel];
+
+            if let Some(exit_record) = self.boundary.hit(&ray, Interval::new(t_min, distance)) {
+                // The walk reached the boundary before the next scattering event
+                let transmittance = (-self.sigma_t * exit_record.t).exp();
+                let pdf = (transmittance.x + transmittance.y + transmittance.z) / 3.0;
+                throughput *= transmittance / pdf;
+
+                let eta = if exit_record.front_face {
+                    self.index_of_refraction
+                } else {
+                    1.0 / self.index_of_refraction
+                };
+                let (attenuation, scattered_ray, lobe) =
+                    scatter_microfacet_dielectric(&ray, &exit_record, &smooth, eta)?;
+                throughput *= attenuation;
+
+                // The walk leaves through the smooth boundary
+                if scattered_ray.direction.dot(exit_record.normal) < 0.0 {
+                    return Some((throughput, scattered_ray, lobe));
+                }
+                ray = Ray::new(
+                    scattered_ray.origin,
+                    scattered_ray.direction.normalize(),
+                    ray.time,
+                );
+                t_min = 1e-4;
+            } else {
+                // Scatter isotropically inside the medium
+                let transmittance = (-self.sigma_t * distance).exp();
+                let density = self.sigma_t * transmittance;
+                let pdf = (density.x + density.y + density.z) / 3.0;
+                throughput *= self.albedo * self.sigma_t * transmittance / pdf;
+
+                ray = Ray::new(ray.at(distance), random_unit_vertor(), ray.time);
+                t_min = 0.0;
+            }
+
+            // Russian roulette once the walk has lost energy to absorption
+            let survival = throughput.max_element();
+            if step > 8 && survival < 1.0 {
+                if random_f64() >= survival {
+                    return None;
+                }
+                throughput /= survival;
+            }
+        }
+
+        None
+    }
+
+    fn eval(&self, _in_ray: &Ray, hit_record: &HitRecord, direction: DVec3) -> Color {
+        // Light enters here and leaves somewhere else, so only a diffuse approximation of the
+        // light leaving near the entry point is available
+        let cos_theta = direction.dot(hit_record.normal);
+        if !hit_record.front_face || cos_theta <= 0.0 {
+            return Color::ZERO;
+        }
+
+        self.diffuse_reflectance() * cos_theta / PI
+    }
+
+    fn pdf(&self, _in_ray: &Ray, _hit_record: &HitRecord, _direction: DVec3) -> f64 {
+        0.0
+    }
+
+    fn is_evaluable(&self) -> bool {
+        false
+    }
+}
@@ -3,6 +3,12 @@ use std::{path::Path, sync::Arc};
 
 pub trait Texture: Sync + Send {
     fn value(&self, u: f64, v: f64, point: &Point3) -> Color;
+
+    /// Returns the texture as a single scalar, e.g. for roughness or mask maps.
+    fn scalar_value(&self, u: f64, v: f64, point: &Point3) -> f64 {
+        let color = self.value(u, v, point);
+        (color.x + color.y + color.z) / 3.0
+    }
 }
 
 #[derive(Default)]
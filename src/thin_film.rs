@@ -0,0 +1,169 @@
+use crate::{
+    color::Color,
+    hittable::HitRecord,
+    texture::{SolidColor, Texture},
+};
+use std::{
+    f64::consts::PI,
+    ops::{Add, Div, Mul, Sub},
+    sync::Arc,
+};
+
+/// A thin dielectric film on top of a surface, such as a soap bubble, an oil slick or the oxide
+/// layer of anodised metal. Thickness is given in nanometres and may vary with a texture.
+pub struct ThinFilm {
+    thickness: Arc<dyn Texture>,
+    index_of_refraction: f64,
+}
+
+impl ThinFilm {
+    // Representative wavelengths, in nanometres, of the red, green and blue channels
+    const WAVELENGTHS: [f64; 3] = [650.0, 532.0, 450.0];
+
+    pub fn new(thickness: Arc<dyn Texture>, index_of_refraction: f64) -> Self {
+        Self {
+            thickness,
+            index_of_refraction,
+        }
+    }
+
+    pub fn from_thickness(thickness: f64, index_of_refraction: f64) -> Self {
+        Self::new(
+            Arc::new(SolidColor::from_rgb(thickness, thickness, thickness)),
+            index_of_refraction,
+        )
+    }
+
+    /// Returns the reflectance of the film lying between an incident medium of index
+    /// `eta_incident` and a substrate of complex index `eta + i k` (given per channel), using
+    /// the Airy summation of all internal reflections in the film.
+    pub fn reflectance(
+        &self,
+        hit_record: &HitRecord,
+        cos_theta_i: f64,
+        eta_incident: f64,
+        eta: Color,
+        k: Color,
+    ) -> Color {
+        let thickness = self
+            .thickness
+            .scalar_value(hit_record.u, hit_record.v, &hit_record.point)
+            .max(0.0);
+        let n_1 = Complex::real(eta_incident);
+        let n_2 = Complex::real(self.index_of_refraction);
+
+        let cos_theta_1 = Complex::real(cos_theta_i.abs().min(1.0));
+        let sin2_theta_1 = Complex::real(1.0) - cos_theta_1 * cos_theta_1;
+        let cos_in =
+            |n: Complex| (Complex::real(1.0) - sin2_theta_1 * (n_1 / n) * (n_1 / n)).sqrt();
+        let cos_theta_2 = cos_in(n_2);
+
+        let mut reflectance = [0.0; 3];
+        for (channel, wavelength) in Self::WAVELENGTHS.iter().enumerate() {
+            let n_3 = Complex::new(eta[channel], k[channel]);
+            let cos_theta_3 = cos_in(n_3);
+
+            // Phase difference accumulated by one round trip through the film
+            let delta = Complex::real(4.0 * PI * thickness / wavelength) * n_2 * cos_theta_2;
+            let phase = (Complex::new(0.0, 1.0) * delta).exp();
+
+            let airy = |r_12: Complex, r_23: Complex| {
+                let r = (r_12 + r_23 * phase) / (Complex::real(1.0) + r_12 * r_23 * phase);
+                r.norm_squared()
+            };
+
+            let r_s = airy(
+                fresnel_s(n_1, cos_theta_1, n_2, cos_theta_2),
+                fresnel_s(n_2, cos_theta_2, n_3, cos_theta_3),
+            );
+            let r_p = airy(
+                fresnel_p(n_1, cos_theta_1, n_2, cos_theta_2),
+                fresnel_p(n_2, cos_theta_2, n_3, cos_theta_3),
+            );
+            reflectance[channel] = f64::min((r_s + r_p) / 2.0, 1.0);
+        }
+
+        Color::from(reflectance)
+    }
+}
+
+// Fresnel amplitude coefficients for s- and p-polarized light
+fn fresnel_s(n_i: Complex, cos_i: Complex, n_t: Complex, cos_t: Complex) -> Complex {
+    (n_i * cos_i - n_t * cos_t) / (n_i * cos_i + n_t * cos_t)
+}
+
+fn fresnel_p(n_i: Complex, cos_i: Complex, n_t: Complex, cos_t: Complex) -> Complex {
+    (n_t * cos_i - n_i * cos_t) / (n_t * cos_i + n_i * cos_t)
+}
+
+#[derive(Clone, Copy)]
+struct Complex {
+    re: f64,
+    im: f64,
+}
+
+impl Complex {
+    fn new(re: f64, im: f64) -> Self {
+        Self { re, im }
+    }
+
+    fn real(re: f64) -> Self {
+        Self { re, im: 0.0 }
+    }
+
+    fn norm_squared(&self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+
+    fn exp(&self) -> Self {
+        let magnitude = self.re.exp();
+        Self::new(magnitude * self.im.cos(), magnitude * self.im.sin())
+    }
+
+    /// Principal square root, with a non-negative real part.
+    fn sqrt(&self) -> Self {
+        let norm = self.norm_squared().sqrt();
+        let re = ((norm + self.re) / 2.0).max(0.0).sqrt();
+        let im = ((norm - self.re) / 2.0).max(0.0).sqrt();
+        Self::new(re, if self.im < 0.0 { -im } else { im })
+    }
+}
+
+impl Add for Complex {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self::Output {
+        Self::new(self.re + other.re, self.im + other.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self::Output {
+        Self::new(self.re - other.re, self.im - other.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self::Output {
+        Self::new(
+            self.re * other.re - self.im * other.im,
+            self.re * other.im + self.im * other.re,
+        )
+    }
+}
+
+impl Div for Complex {
+    type Output = Self;
+
+    fn div(self, other: Self) -> Self::Output {
+        let denom = other.norm_squared();
+        Self::new(
+            (self.re * other.re + self.im * other.im) / denom,
+            (self.im * other.re - self.re * other.im) / denom,
+        )
+    }
+}